@@ -1,15 +1,30 @@
+use chrono::{DateTime, Local};
 use egui::Vec2;
 use egui_extras::{Column, RetainedImage, TableBuilder};
-use log::warn;
+use log::{debug, warn};
 use rfd::FileDialog;
 use rodio::{OutputStream, OutputStreamHandle, Sink};
 use std::io::BufReader;
 use std::{
     collections::{HashMap, HashSet},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, SystemTime},
 };
 
-use crate::{apply_changes, generate_song_list, Action, Song};
+use crate::cleanup::{CleanupItem, CleanupKind, CleanupTask};
+use crate::duplicate::{find_duplicates, songs_to_delete, DuplicateGroup, DuplicateKind, KeepRule};
+use crate::filter::{LevelIdFilter, SearchTexts, SongFilter};
+use crate::invalid::{open_in_file_manager, quarantine, InvalidReason};
+use crate::journal::{Journal, JournalBatch};
+use crate::naming::{NamingTemplate, PRESETS, TOKENS};
+use crate::plan::{ChangePlan, Conflict, Resolution};
+use crate::trash::{Trash, TrashEntry};
+use crate::watcher::{FolderChange, SongFolderWatcher};
+use crate::{
+    apply_changes, default_parallelism, Action, BeatmapCharacteristic, ChangeResult, Playlist,
+    PlaylistMatch, ScanMessage, ScanOptions, ScanTask, Song, DEFAULT_ID,
+};
 fn setup_custom_fonts(ctx: &egui::Context) {
     // Start with the default fonts (we will be adding to them rather than replacing them).
     let mut fonts = egui::FontDefinitions::default();
@@ -39,13 +54,551 @@ fn setup_custom_fonts(ctx: &egui::Context) {
     ctx.set_fonts(fonts);
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+enum SongColumn {
+    Name,
+    SongAuthor,
+    Mapper,
+    Bpm,
+    LevelId,
+    Characteristics,
+    HighestDifficulty,
+    Duration,
+    FolderSize,
+    DateAdded,
+}
+
+impl SongColumn {
+    const ALL: [SongColumn; 10] = [
+        SongColumn::Name,
+        SongColumn::SongAuthor,
+        SongColumn::Mapper,
+        SongColumn::Bpm,
+        SongColumn::LevelId,
+        SongColumn::Characteristics,
+        SongColumn::HighestDifficulty,
+        SongColumn::Duration,
+        SongColumn::FolderSize,
+        SongColumn::DateAdded,
+    ];
+
+    fn label(&self) -> String {
+        match self {
+            SongColumn::Name => t!("column_name"),
+            SongColumn::SongAuthor => t!("column_song_author"),
+            SongColumn::Mapper => t!("column_mapper"),
+            SongColumn::Bpm => t!("column_bpm"),
+            SongColumn::LevelId => t!("column_level_id"),
+            SongColumn::Characteristics => t!("column_characteristics"),
+            SongColumn::HighestDifficulty => t!("column_highest_difficulty"),
+            SongColumn::Duration => t!("column_duration"),
+            SongColumn::FolderSize => t!("column_folder_size"),
+            SongColumn::DateAdded => t!("column_date_added"),
+        }
+    }
+
+    fn text(&self, song: &Song) -> String {
+        match self {
+            SongColumn::Name => song.song_name.clone(),
+            SongColumn::SongAuthor => song.song_author_name.clone(),
+            SongColumn::Mapper => song.level_author_name.clone(),
+            SongColumn::Bpm => song.beats_per_minute.to_string(),
+            SongColumn::LevelId => song.level_id.clone(),
+            SongColumn::Characteristics => song
+                .difficulty_beatmap_sets
+                .iter()
+                .map(|set| set.beatmap_characteristic_name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", "),
+            SongColumn::HighestDifficulty => match song.highest_difficulty() {
+                Some(beatmap) => beatmap.difficulty.clone(),
+                None => String::new(),
+            },
+            SongColumn::Duration => song.duration.map(format_duration).unwrap_or_default(),
+            SongColumn::FolderSize => format_size(song.folder_size),
+            SongColumn::DateAdded => song.date_added.map(format_time).unwrap_or_default(),
+        }
+    }
+
+    fn compare(&self, a: &Song, b: &Song) -> std::cmp::Ordering {
+        match self {
+            SongColumn::Name => a.song_name.cmp(&b.song_name),
+            SongColumn::SongAuthor => a.song_author_name.cmp(&b.song_author_name),
+            SongColumn::Mapper => a.level_author_name.cmp(&b.level_author_name),
+            SongColumn::Bpm => a.beats_per_minute.total_cmp(&b.beats_per_minute),
+            // Level ids are hex numbers, compare them as such so that `ff` comes before `100`.
+            SongColumn::LevelId => {
+                (a.level_id.len(), &a.level_id).cmp(&(b.level_id.len(), &b.level_id))
+            }
+            SongColumn::Characteristics => self.text(a).cmp(&self.text(b)),
+            SongColumn::HighestDifficulty => a
+                .highest_difficulty()
+                .map(|beatmap| beatmap.difficulty_rank)
+                .cmp(
+                    &b.highest_difficulty()
+                        .map(|beatmap| beatmap.difficulty_rank),
+                ),
+            SongColumn::Duration => a.duration.cmp(&b.duration),
+            SongColumn::FolderSize => a.folder_size.cmp(&b.folder_size),
+            SongColumn::DateAdded => a.date_added.cmp(&b.date_added),
+        }
+    }
+}
+
+/// Settings kept between launches.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct Settings {
+    visible_columns: Vec<SongColumn>,
+    sort_column: SongColumn,
+    sort_ascending: bool,
+    scan_parallelism: usize,
+    /// Days after which trashed songs get deleted permanently, 0 keeps them forever.
+    trash_retention_days: u64,
+    naming: NamingTemplate,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            visible_columns: vec![
+                SongColumn::Name,
+                SongColumn::Mapper,
+                SongColumn::Bpm,
+                SongColumn::Duration,
+            ],
+            sort_column: SongColumn::Name,
+            sort_ascending: true,
+            scan_parallelism: default_parallelism(),
+            trash_retention_days: 30,
+            naming: NamingTemplate::default(),
+        }
+    }
+}
+
+fn sort_song_list(song_list: &mut [Song], settings: &Settings) {
+    song_list.sort_by(|a, b| {
+        let ordering = settings.sort_column.compare(a, b);
+        if settings.sort_ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    });
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Songs selected in the table, keyed by folder path.
+#[derive(Default)]
+struct Selection {
+    paths: HashSet<PathBuf>,
+    /// Song the next shift click extends the selection from.
+    anchor: Option<PathBuf>,
+}
+
+impl Selection {
+    fn contains(&self, song: &Song) -> bool {
+        self.paths.contains(&song.song_folder_path)
+    }
+
+    fn clear(&mut self) {
+        self.paths.clear();
+        self.anchor = None;
+    }
+
+    /// Forget the song folder `path`, it is no longer listed.
+    fn remove(&mut self, path: &Path) {
+        self.paths.remove(path);
+        if self.anchor.as_deref() == Some(path) {
+            self.anchor = None;
+        }
+    }
+
+    /// Update the selection for a click on `songs[index]`, like a file manager does: shift
+    /// selects the range from the anchor, ctrl toggles one song and a plain click selects only it.
+    fn click(&mut self, songs: &[&Song], index: usize, modifiers: egui::Modifiers) {
+        let path = &songs[index].song_folder_path;
+        let anchor_index = self.anchor.as_ref().and_then(|anchor| {
+            songs
+                .iter()
+                .position(|song| &song.song_folder_path == anchor)
+        });
+        match anchor_index {
+            Some(anchor_index) if modifiers.shift => {
+                if !modifiers.command {
+                    self.paths.clear();
+                }
+                let range = anchor_index.min(index)..=anchor_index.max(index);
+                for song in &songs[range] {
+                    self.paths.insert(song.song_folder_path.clone());
+                }
+                return;
+            }
+            _ if modifiers.command => {
+                if !self.paths.remove(path) {
+                    self.paths.insert(path.clone());
+                }
+            }
+            _ => {
+                self.paths.clear();
+                self.paths.insert(path.clone());
+            }
+        }
+        self.anchor = Some(path.clone());
+    }
+
+    fn songs<'a>(&self, song_list: &'a [Song]) -> Vec<&'a Song> {
+        song_list
+            .iter()
+            .filter(|song| self.contains(song))
+            .collect()
+    }
+}
+
+/// Table of the songs matching `song_filter`, clicking a header sorts by that column.
+fn song_table(
+    ui: &mut egui::Ui,
+    song_list: &mut [Song],
+    song_filter: &SongFilter,
+    search_texts: &SearchTexts,
+    settings: &mut Settings,
+    selection: &mut Selection,
+    current_song: &mut Option<Song>,
+) {
+    let mut sort_changed = false;
+    let matcher = song_filter.matcher(search_texts);
+    let songs: Vec<&Song> = song_list
+        .iter()
+        .filter(|song| matcher.matches(song))
+        .collect();
+    let mut table = TableBuilder::new(ui)
+        .striped(true)
+        .resizable(true)
+        .cell_layout(egui::Layout::left_to_right(egui::Align::Center));
+    for column in &settings.visible_columns {
+        table = match column {
+            SongColumn::Name => table.column(Column::initial(200.0).at_least(40.0).clip(true)),
+            _ => table.column(Column::initial(80.0).at_least(20.0).clip(true)),
+        };
+    }
+    table
+        .header(20.0, |mut header| {
+            for column in &settings.visible_columns {
+                header.col(|ui| {
+                    let sorted = settings.sort_column == *column;
+                    let text = match (sorted, settings.sort_ascending) {
+                        (true, true) => format!("{} ⏶", column.label()),
+                        (true, false) => format!("{} ⏷", column.label()),
+                        (false, _) => column.label(),
+                    };
+                    if ui.selectable_label(sorted, text).clicked() {
+                        if sorted {
+                            settings.sort_ascending = !settings.sort_ascending;
+                        } else {
+                            settings.sort_column = *column;
+                            settings.sort_ascending = true;
+                        }
+                        sort_changed = true;
+                    }
+                });
+            }
+        })
+        .body(|body| {
+            body.rows(20.0, songs.len(), |index, mut row| {
+                let song = songs[index];
+                for column in &settings.visible_columns {
+                    row.col(|ui| {
+                        let text = column.text(song);
+                        if *column == SongColumn::Name {
+                            let selected = selection.contains(song);
+                            if ui.selectable_label(selected, text).clicked() {
+                                let modifiers = ui.input().modifiers;
+                                selection.click(&songs, index, modifiers);
+                                *current_song = Some(song.clone());
+                            }
+                        } else {
+                            ui.label(text);
+                        }
+                    });
+                }
+            });
+        });
+    if sort_changed {
+        sort_song_list(song_list, settings);
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Filter conditions above the song list, with a count of the songs that match.
+fn filter_bar(
+    ui: &mut egui::Ui,
+    song_list: &[Song],
+    song_filter: &mut SongFilter,
+    search_texts: &SearchTexts,
+) {
+    let mut characteristics: Vec<BeatmapCharacteristic> = Vec::new();
+    let mut difficulties: Vec<String> = Vec::new();
+    for song in song_list {
+        for difficulty_beatmap_set in &song.difficulty_beatmap_sets {
+            let characteristic = &difficulty_beatmap_set.beatmap_characteristic_name;
+            if !characteristics.contains(characteristic) {
+                characteristics.push(characteristic.clone());
+            }
+            for difficulty_beatmap in &difficulty_beatmap_set.difficulty_beatmaps {
+                if !difficulties.contains(&difficulty_beatmap.difficulty) {
+                    difficulties.push(difficulty_beatmap.difficulty.clone());
+                }
+            }
+        }
+    }
+    characteristics.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    difficulties.sort();
+
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(&mut song_filter.text).hint_text(t!("search_hint")));
+        if ui.button(t!("clear_filter")).clicked() {
+            *song_filter = SongFilter::default();
+        }
+    });
+    ui.collapsing(t!("filter"), |ui| {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut song_filter.bpm_enabled, t!("bpm_range"));
+            ui.add_enabled(
+                song_filter.bpm_enabled,
+                egui::DragValue::new(&mut song_filter.min_bpm).clamp_range(0.0..=1000.0),
+            );
+            ui.label("-");
+            ui.add_enabled(
+                song_filter.bpm_enabled,
+                egui::DragValue::new(&mut song_filter.max_bpm).clamp_range(0.0..=1000.0),
+            );
+        });
+        egui::ComboBox::from_label(t!("characteristic_filter"))
+            .selected_text(match &song_filter.characteristic {
+                Some(characteristic) => characteristic.as_str().to_string(),
+                None => t!("all"),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut song_filter.characteristic, None, t!("all"));
+                for characteristic in characteristics {
+                    let text = characteristic.as_str().to_string();
+                    ui.selectable_value(
+                        &mut song_filter.characteristic,
+                        Some(characteristic),
+                        text,
+                    );
+                }
+            });
+        egui::ComboBox::from_label(t!("difficulty_filter"))
+            .selected_text(match &song_filter.difficulty {
+                Some(difficulty) => difficulty.clone(),
+                None => t!("all"),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut song_filter.difficulty, None, t!("all"));
+                for difficulty in difficulties {
+                    let text = difficulty.clone();
+                    ui.selectable_value(&mut song_filter.difficulty, Some(difficulty), text);
+                }
+            });
+        egui::ComboBox::from_label(t!("level_id_filter"))
+            .selected_text(match song_filter.level_id {
+                LevelIdFilter::Any => t!("all"),
+                LevelIdFilter::Known => t!("level_id_known"),
+                LevelIdFilter::Unknown => t!("level_id_unknown"),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut song_filter.level_id, LevelIdFilter::Any, t!("all"));
+                ui.selectable_value(
+                    &mut song_filter.level_id,
+                    LevelIdFilter::Known,
+                    t!("level_id_known"),
+                );
+                ui.selectable_value(
+                    &mut song_filter.level_id,
+                    LevelIdFilter::Unknown,
+                    t!("level_id_unknown"),
+                );
+            });
+    });
+    if !song_filter.is_empty() {
+        let matcher = song_filter.matcher(search_texts);
+        let matched = song_list
+            .iter()
+            .filter(|song| matcher.matches(song))
+            .count();
+        ui.label(t!(
+            "filter_result",
+            matched = &matched.to_string(),
+            total = &song_list.len().to_string()
+        ));
+    }
+}
+
+fn naming_editor(ui: &mut egui::Ui, naming: &mut NamingTemplate, preview_song: Option<&Song>) {
+    egui::ComboBox::from_label(t!("naming_preset"))
+        .selected_text(
+            PRESETS
+                .iter()
+                .find(|(_, template)| *template == naming.template)
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_else(|| t!("custom")),
+        )
+        .show_ui(ui, |ui| {
+            for (name, template) in PRESETS {
+                if ui
+                    .selectable_label(naming.template == *template, *name)
+                    .on_hover_text(*template)
+                    .clicked()
+                {
+                    naming.template = template.to_string();
+                }
+            }
+        });
+    ui.text_edit_singleline(&mut naming.template)
+        .on_hover_text(TOKENS.join(" "));
+    ui.checkbox(&mut naming.keep_unicode, t!("keep_unicode"));
+    if let Some(song) = preview_song {
+        ui.separator();
+        ui.label(t!("naming_preview"));
+        ui.label(song.get_canonical_name(naming));
+    }
+}
+
+fn invalid_reason_label(reason: &InvalidReason) -> String {
+    match reason {
+        InvalidReason::NotADirectory => t!("invalid_not_a_directory"),
+        InvalidReason::ReadFailed { message, .. } => t!("invalid_read_failed", message = message),
+        InvalidReason::NoInfoDat => t!("invalid_no_info_dat"),
+        InvalidReason::InfoDatParse {
+            line,
+            column,
+            message,
+            ..
+        } => t!(
+            "invalid_info_dat_parse",
+            line = &line.to_string(),
+            column = &column.to_string(),
+            message = message
+        ),
+        InvalidReason::MissingFile(name) => t!("invalid_missing_file", name = name),
+        InvalidReason::HashReadFailed { file, message, .. } => {
+            t!("invalid_hash_read_failed", name = file, message = message)
+        }
+        InvalidReason::MissingAudio(name) => t!("invalid_missing_audio", name = name),
+        InvalidReason::MissingCover(name) => t!("invalid_missing_cover", name = name),
+        InvalidReason::UnknownCharacteristic(name) => {
+            t!("invalid_unknown_characteristic", name = name)
+        }
+    }
+}
+
+fn cleanup_kind_label(kind: CleanupKind) -> String {
+    match kind {
+        CleanupKind::StrayFile => t!("cleanup_stray_file"),
+        CleanupKind::EmptyFolder => t!("cleanup_empty_folder"),
+        CleanupKind::UnreferencedFile => t!("cleanup_unreferenced_file"),
+        CleanupKind::OsJunk => t!("cleanup_os_junk"),
+    }
+}
+
+fn folder_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn conflict_label(conflict: Conflict) -> String {
+    match conflict {
+        Conflict::AlreadyCanonical => t!("conflict_already_canonical"),
+        Conflict::DestinationExists => t!("conflict_destination_exists"),
+        Conflict::DuplicateDestination => t!("conflict_duplicate_destination"),
+        Conflict::NameTooLong => t!("conflict_name_too_long"),
+        Conflict::InvalidName => t!("conflict_invalid_name"),
+    }
+}
+
 pub struct ManagerApp {
     song_folder: PathBuf,
     song_list: Vec<Song>,
+    settings: Settings,
     list_outdated: bool,
-    invalid_path: HashSet<PathBuf>,
+    /// Ignore the library index on the next scan.
+    full_rescan: bool,
+    scan_task: Option<ScanTask>,
+    /// Picks up song folders changed by other programs once the scan is done.
+    watcher: Option<SongFolderWatcher>,
+    invalid_path: HashMap<PathBuf, InvalidReason>,
+    /// Folders the last scan didn't get to because it was cancelled.
+    skipped_path: Vec<PathBuf>,
+    /// Why the last scan couldn't look up the level ids of some songs.
+    lookup_error: Option<String>,
+    show_invalid: bool,
     pending_changes: HashMap<Song, Action>,
+    /// Dry run of the pending changes shown for review before committing.
+    change_plan: Option<ChangePlan>,
+    /// Results of the last commit, shown until dismissed.
+    commit_results: Option<Vec<ChangeResult>>,
     current_song: Option<Song>,
+    song_filter: SongFilter,
+    /// Search text of the songs in `song_list`, kept apart as `Song` is a map key.
+    search_texts: SearchTexts,
+    selection: Selection,
+    show_playlist: bool,
+    show_duplicates: bool,
+    /// Found when the duplicates window opens, dropped when the song list changes.
+    duplicates: Option<Vec<DuplicateGroup>>,
+    duplicate_keep_rule: KeepRule,
+    show_cleanup: bool,
+    cleanup_items: Vec<CleanupItem>,
+    /// Search for cleanup items started when the cleanup window opens.
+    cleanup_task: Option<CleanupTask>,
+    show_trash: bool,
+    trash_entries: Vec<TrashEntry>,
+    /// Batch reversed by "Undo Last Commit".
+    last_commit: Option<JournalBatch>,
+    /// Changes the last undo couldn't revert, shown until dismissed.
+    undo_errors: Option<Vec<String>>,
+    playlist_title: String,
+    playlist_author: String,
+    playlist_description: String,
+    playlist_cover: Option<Song>,
+    playlist_songs: Vec<Song>,
+    imported_playlist: Option<Playlist>,
+    imported_playlist_matches: Vec<PlaylistMatch>,
     _stream: Option<OutputStream>,
     stream_handle: Option<OutputStreamHandle>,
     sink: Option<Sink>,
@@ -63,10 +616,40 @@ impl Default for ManagerApp {
         Self {
             song_folder: std::env::current_dir().unwrap(),
             song_list: Vec::new(),
+            settings: Settings::default(),
             list_outdated: false,
-            invalid_path: HashSet::new(),
+            full_rescan: false,
+            scan_task: None,
+            watcher: None,
+            invalid_path: HashMap::new(),
+            skipped_path: Vec::new(),
+            lookup_error: None,
+            show_invalid: false,
             pending_changes: HashMap::new(),
+            change_plan: None,
+            commit_results: None,
             current_song: None,
+            song_filter: SongFilter::default(),
+            search_texts: SearchTexts::default(),
+            selection: Selection::default(),
+            show_playlist: false,
+            show_duplicates: false,
+            duplicates: None,
+            duplicate_keep_rule: KeepRule::Newest,
+            show_cleanup: false,
+            cleanup_items: Vec::new(),
+            cleanup_task: None,
+            show_trash: false,
+            trash_entries: Vec::new(),
+            last_commit: None,
+            undo_errors: None,
+            playlist_title: String::new(),
+            playlist_author: String::new(),
+            playlist_description: String::new(),
+            playlist_cover: None,
+            playlist_songs: Vec::new(),
+            imported_playlist: None,
+            imported_playlist_matches: Vec::new(),
             _stream,
             stream_handle,
             sink: None,
@@ -80,29 +663,209 @@ impl ManagerApp {
         // This is also where you can customized the look at feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
         setup_custom_fonts(&cc.egui_ctx);
-        Default::default()
+        let mut app: ManagerApp = Default::default();
+        if let Some(storage) = cc.storage {
+            if let Some(settings) = eframe::get_value(storage, eframe::APP_KEY) {
+                app.settings = settings;
+            }
+        }
+        app
     }
 }
 
 impl eframe::App for ManagerApp {
+    /// Called by the frame work to save state before shutdown.
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings);
+    }
+
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let Self {
             song_folder,
             song_list,
+            settings,
             list_outdated,
+            full_rescan,
+            scan_task,
+            watcher,
             invalid_path,
+            skipped_path,
+            lookup_error,
+            show_invalid,
             pending_changes,
+            change_plan,
+            commit_results,
             current_song,
+            song_filter,
+            search_texts,
+            selection,
+            show_playlist,
+            show_duplicates,
+            duplicates,
+            duplicate_keep_rule,
+            show_cleanup,
+            cleanup_items,
+            cleanup_task,
+            show_trash,
+            trash_entries,
+            last_commit,
+            undo_errors,
+            playlist_title,
+            playlist_author,
+            playlist_description,
+            playlist_cover,
+            playlist_songs,
+            imported_playlist,
+            imported_playlist_matches,
             _stream,
             stream_handle,
             sink,
         } = self;
 
         if *list_outdated {
-            (*song_list, *invalid_path) = generate_song_list(song_folder);
+            if let Some(task) = scan_task {
+                task.cancel();
+            }
+            let trash = Trash::new(song_folder);
+            let journal = Journal::new(song_folder);
+            if settings.trash_retention_days > 0 {
+                trash.purge_expired(
+                    Duration::from_secs(settings.trash_retention_days * 24 * 60 * 60),
+                    &journal.referenced_trash_ids(),
+                );
+            }
+            *trash_entries = trash.entries();
+            *last_commit = journal.last_undoable();
+            *song_list = Vec::new();
+            search_texts.clear();
+            *duplicates = None;
+            selection.clear();
+            *invalid_path = HashMap::new();
+            *skipped_path = Vec::new();
+            *lookup_error = None;
+            *scan_task = Some(ScanTask::start(
+                song_folder.clone(),
+                ScanOptions {
+                    parallelism: settings.scan_parallelism,
+                    full_rescan: *full_rescan,
+                    update_index: true,
+                },
+            ));
             *list_outdated = false;
+            *full_rescan = false;
+            if watcher
+                .as_ref()
+                .map_or(true, |watcher| watcher.song_path() != song_folder.as_path())
+            {
+                *watcher = match SongFolderWatcher::start(song_folder.clone(), ctx.clone()) {
+                    Ok(watcher) => Some(watcher),
+                    Err(error) => {
+                        warn!("Watch song folder failed. {}", error);
+                        None
+                    }
+                };
+            }
+        }
+
+        if let Some(task) = scan_task {
+            // Check before draining so that no message sent before the end is left behind.
+            let finished = task.is_finished();
+            let mut list_changed = false;
+            for message in task.receiver.try_iter() {
+                match message {
+                    ScanMessage::Song(song) => {
+                        song_list.push(*song);
+                        list_changed = true;
+                    }
+                    ScanMessage::Invalid(path, reason) => {
+                        invalid_path.insert(path, reason);
+                    }
+                    ScanMessage::Skipped(path) => {
+                        debug!("Skipped loading {}.", path.as_path().display());
+                        skipped_path.push(path);
+                    }
+                    ScanMessage::LevelIds(level_ids) => {
+                        for song in song_list.iter_mut() {
+                            if song.level_id != DEFAULT_ID {
+                                continue;
+                            }
+                            if let Some(id) = level_ids.get(&song.level_hash) {
+                                song.level_id = id.clone();
+                            }
+                        }
+                        list_changed = true;
+                    }
+                    ScanMessage::LookupFailed(error) => {
+                        *lookup_error = Some(error.to_string());
+                    }
+                }
+            }
+            if list_changed {
+                sort_song_list(song_list, settings);
+                search_texts.update(song_list);
+                *duplicates = None;
+            }
+            if finished {
+                *scan_task = None;
+                if let Some(playlist) = imported_playlist {
+                    *imported_playlist_matches = playlist.match_songs(song_list);
+                }
+            } else {
+                ctx.request_repaint();
+            }
+        }
+        // Changes that happen during a scan wait here until it is done, so that a folder isn't
+        // listed twice.
+        if let (None, Some(watcher)) = (&scan_task, &watcher) {
+            let mut list_changed = false;
+            for change in watcher.receiver.try_iter() {
+                let path = match &change {
+                    FolderChange::Song(song) => song.song_folder_path.clone(),
+                    FolderChange::Invalid(path, _) | FolderChange::Removed(path) => path.clone(),
+                };
+                song_list.retain(|song| song.song_folder_path != path);
+                search_texts.remove(&path);
+                invalid_path.remove(&path);
+                let is_current = current_song
+                    .as_ref()
+                    .map_or(false, |song| song.song_folder_path == path);
+                match change {
+                    FolderChange::Song(song) => {
+                        if let Some(action) = pending_changes.remove(&song) {
+                            pending_changes.insert((*song).clone(), action);
+                        }
+                        if is_current {
+                            *current_song = Some((*song).clone());
+                        }
+                        song_list.push(*song);
+                    }
+                    FolderChange::Invalid(path, reason) => {
+                        if is_current {
+                            *current_song = None;
+                        }
+                        selection.remove(&path);
+                        invalid_path.insert(path, reason);
+                    }
+                    FolderChange::Removed(path) => {
+                        if is_current {
+                            *current_song = None;
+                        }
+                        pending_changes.retain(|song, _| song.song_folder_path != path);
+                        selection.remove(&path);
+                    }
+                }
+                list_changed = true;
+            }
+            if list_changed {
+                sort_song_list(song_list, settings);
+                search_texts.update(song_list);
+                *duplicates = None;
+                if let Some(playlist) = imported_playlist {
+                    *imported_playlist_matches = playlist.match_songs(song_list);
+                }
+            }
         }
 
         egui::TopBottomPanel::top("menu_panel").show(ctx, |ui| {
@@ -114,28 +877,822 @@ impl eframe::App for ManagerApp {
                         *list_outdated = true;
                     }
                 }
+                if ui.button(t!("playlist")).clicked() {
+                    *show_playlist = !*show_playlist;
+                }
+                if ui.button(t!("duplicates")).clicked() {
+                    *show_duplicates = !*show_duplicates;
+                }
+                if ui
+                    .button(t!(
+                        "invalid_folders",
+                        count = &invalid_path.len().to_string()
+                    ))
+                    .clicked()
+                {
+                    *show_invalid = !*show_invalid;
+                }
+                if ui.button(t!("cleanup")).clicked() {
+                    *show_cleanup = !*show_cleanup;
+                    if *show_cleanup {
+                        *cleanup_task =
+                            Some(CleanupTask::start(song_folder.clone(), song_list.clone()));
+                    }
+                }
+                if ui.button(t!("trash")).clicked() {
+                    *show_trash = !*show_trash;
+                    *trash_entries = Trash::new(song_folder).entries();
+                }
+                if ui.button(t!("import_playlist")).clicked() {
+                    let select_file = FileDialog::new()
+                        .add_filter("Beat Saber Playlist", &["bplist", "json"])
+                        .pick_file();
+                    if let Some(select_file) = select_file {
+                        match Playlist::from_path(&select_file) {
+                            Ok(playlist) => {
+                                *imported_playlist_matches = playlist.match_songs(song_list);
+                                *imported_playlist = Some(playlist);
+                            }
+                            Err(error) => {
+                                warn!("Import playlist failed.{}", error);
+                            }
+                        }
+                    }
+                }
+                ui.menu_button(t!("naming"), |ui| {
+                    naming_editor(
+                        ui,
+                        &mut settings.naming,
+                        current_song.as_ref().or(song_list.first()),
+                    );
+                });
+                ui.label(t!("scan_parallelism"));
+                ui.add(egui::DragValue::new(&mut settings.scan_parallelism).clamp_range(1..=64))
+                    .on_hover_text(t!("scan_parallelism_hint"));
+                if ui
+                    .button(t!("full_rescan"))
+                    .on_hover_text(t!("full_rescan_hint"))
+                    .clicked()
+                {
+                    *list_outdated = true;
+                    *full_rescan = true;
+                }
                 ui.label(t!("current_working_folder"));
                 ui.label(&(*song_folder.as_path().display().to_string()));
             });
         });
 
-        egui::SidePanel::left("song_list_panel").show(ctx, |ui| {
-            ui.heading(t!("song_list_title"));
+        egui::Window::new(t!("playlist"))
+            .open(show_playlist)
+            .show(ctx, |ui| {
+                egui::Grid::new("playlist_info")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label(t!("playlist_title"));
+                        ui.text_edit_singleline(playlist_title);
+                        ui.end_row();
+                        ui.label(t!("playlist_author"));
+                        ui.text_edit_singleline(playlist_author);
+                        ui.end_row();
+                        ui.label(t!("playlist_description"));
+                        ui.text_edit_multiline(playlist_description);
+                        ui.end_row();
+                        ui.label(t!("playlist_cover"));
+                        egui::ComboBox::from_id_source("playlist_cover")
+                            .selected_text(match playlist_cover {
+                                Some(song) => song.song_name.clone(),
+                                None => t!("no_cover"),
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(playlist_cover, None, t!("no_cover"));
+                                for song in playlist_songs.iter() {
+                                    ui.selectable_value(
+                                        playlist_cover,
+                                        Some(song.clone()),
+                                        &song.song_name,
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    });
+                ui.separator();
+                let mut withdraw_song = None;
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for (index, song) in playlist_songs.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.button("-").clicked() {
+                                    withdraw_song = Some(index);
+                                }
+                                ui.label(&song.song_name).on_hover_text(
+                                    song.song_folder_path.as_path().display().to_string(),
+                                );
+                            });
+                        }
+                    });
+                if let Some(index) = withdraw_song {
+                    let song = playlist_songs.remove(index);
+                    if playlist_cover.as_ref() == Some(&song) {
+                        *playlist_cover = None;
+                    }
+                }
+                ui.separator();
+                if ui.button(t!("export_playlist")).clicked() && !playlist_songs.is_empty() {
+                    let save_path = FileDialog::new()
+                        .add_filter("Beat Saber Playlist", &["bplist"])
+                        .set_file_name(&format!("{}.bplist", playlist_title))
+                        .save_file();
+                    if let Some(save_path) = save_path {
+                        let playlist = Playlist::new(
+                            playlist_title,
+                            playlist_author,
+                            playlist_description,
+                            playlist_songs,
+                            playlist_cover.as_ref(),
+                        );
+                        if let Err(error) = playlist.save(&save_path) {
+                            warn!("Export playlist failed.{}", error);
+                        }
+                    }
+                }
+            });
 
-            ui.separator();
-            egui::ScrollArea::vertical()
-                .auto_shrink([false; 2])
-                .show(ui, |ui| {
-                    ui.vertical(|ui| {
-                        for song in song_list {
-                            if ui.link(&song.song_name).clicked() {
-                                *current_song = Some(song.clone());
+        if let Some(playlist) = imported_playlist.as_ref() {
+            let mut open = true;
+            egui::Window::new(t!("imported_playlist"))
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.heading(playlist.title());
+                    ui.label(t!("playlist_author_label", author = playlist.author()));
+                    let present = imported_playlist_matches
+                        .iter()
+                        .filter(|entry| entry.song.is_some())
+                        .count();
+                    ui.label(t!(
+                        "playlist_summary",
+                        present = &present.to_string(),
+                        missing = &(imported_playlist_matches.len() - present).to_string()
+                    ));
+                    ui.separator();
+                    egui::ScrollArea::vertical()
+                        .max_height(400.0)
+                        .show(ui, |ui| {
+                            for entry in imported_playlist_matches.iter() {
+                                ui.horizontal(|ui| match &entry.song {
+                                    Some(song) => {
+                                        ui.label("✔").on_hover_text(t!("song_present"));
+                                        if ui
+                                            .link(&song.song_name)
+                                            .on_hover_text(&entry.hash)
+                                            .clicked()
+                                        {
+                                            *current_song = Some(song.clone());
+                                        }
+                                    }
+                                    None => {
+                                        ui.label("✘").on_hover_text(t!("song_missing"));
+                                        ui.label(&entry.song_name).on_hover_text(&entry.hash);
+                                    }
+                                });
                             }
+                        });
+                });
+            if !open {
+                *imported_playlist = None;
+                *imported_playlist_matches = Vec::new();
+            }
+        }
+
+        if !*show_duplicates {
+            *duplicates = None;
+        }
+        egui::Window::new(t!("duplicates"))
+            .open(show_duplicates)
+            .show(ctx, |ui| {
+                let duplicates = duplicates.get_or_insert_with(|| find_duplicates(song_list));
+                ui.horizontal(|ui| {
+                    ui.label(t!("keep"));
+                    ui.selectable_value(duplicate_keep_rule, KeepRule::Newest, t!("keep_newest"));
+                    ui.selectable_value(
+                        duplicate_keep_rule,
+                        KeepRule::CanonicalName,
+                        t!("keep_canonical_name"),
+                    );
+                });
+                ui.label(t!(
+                    "duplicate_summary",
+                    count = &duplicates.len().to_string()
+                ));
+                if ui.button(t!("delete_all_duplicates")).clicked() {
+                    for song in songs_to_delete(duplicates, *duplicate_keep_rule, &settings.naming)
+                    {
+                        pending_changes.insert(song.clone(), Action::Delete);
+                    }
+                }
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        for (index, group) in duplicates.iter().enumerate() {
+                            let keeper = group.keeper(*duplicate_keep_rule, &settings.naming);
+                            ui.horizontal(|ui| {
+                                ui.strong(&group.songs[0].song_name);
+                                match group.kind {
+                                    DuplicateKind::SameHash => {
+                                        ui.label(t!("same_hash")).on_hover_text(&group.key)
+                                    }
+                                    DuplicateKind::SameLevelId => ui
+                                        .label(t!("same_level_id", id = &group.key))
+                                        .on_hover_text(t!("same_level_id_hint")),
+                                };
+                                if ui.button(t!("delete_duplicates")).clicked() {
+                                    for song in &group.songs {
+                                        if song != keeper {
+                                            pending_changes.insert(song.clone(), Action::Delete);
+                                        }
+                                    }
+                                }
+                            });
+                            egui::Grid::new(("duplicate_group", index))
+                                .num_columns(4)
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    for song in &group.songs {
+                                        if song == keeper {
+                                            ui.label("★").on_hover_text(t!("kept"));
+                                        } else if pending_changes.get(song) == Some(&Action::Delete)
+                                        {
+                                            ui.label("🗑").on_hover_text(t!("delete_queued"));
+                                        } else {
+                                            ui.label("");
+                                        }
+                                        if ui
+                                            .link(song.song_folder_path.display().to_string())
+                                            .clicked()
+                                        {
+                                            *current_song = Some(song.clone());
+                                        }
+                                        ui.label(format_size(song.folder_size));
+                                        ui.label(
+                                            song.date_modified.map(format_time).unwrap_or_default(),
+                                        );
+                                        ui.end_row();
+                                    }
+                                });
                             ui.separator();
                         }
-                    })
+                    });
+            });
+
+        if let Some(task) = cleanup_task {
+            match task.try_result() {
+                Some(items) => {
+                    *cleanup_items = items;
+                    *cleanup_task = None;
+                }
+                None => ctx.request_repaint(),
+            }
+        }
+
+        egui::Window::new(t!("cleanup"))
+            .open(show_cleanup)
+            .show(ctx, |ui| {
+                if cleanup_task.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(t!("cleanup_searching"));
+                    });
+                    return;
+                }
+                let selected_size: u64 = cleanup_items
+                    .iter()
+                    .filter(|item| item.selected)
+                    .map(|item| item.size)
+                    .sum();
+                ui.horizontal(|ui| {
+                    ui.label(t!(
+                        "cleanup_summary",
+                        count = &cleanup_items.len().to_string(),
+                        size = &format_size(selected_size)
+                    ));
+                    if ui.button(t!("refresh")).clicked() {
+                        *cleanup_task =
+                            Some(CleanupTask::start(song_folder.clone(), song_list.clone()));
+                    }
+                    if ui.button(t!("queue_cleanup")).clicked() {
+                        // Queued like any other delete, so the items are reviewed, journaled
+                        // and can be undone.
+                        for item in cleanup_items.iter().filter(|item| item.selected) {
+                            let mut song = Song::placeholder(&item.path);
+                            song.folder_size = item.size;
+                            pending_changes.insert(song, Action::Delete);
+                        }
+                        cleanup_items.retain(|item| !item.selected);
+                    }
                 });
-        });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("cleanup_items")
+                            .num_columns(4)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for item in cleanup_items.iter_mut() {
+                                    ui.checkbox(&mut item.selected, "");
+                                    ui.label(cleanup_kind_label(item.kind));
+                                    let path = item
+                                        .path
+                                        .strip_prefix(song_folder.as_path())
+                                        .unwrap_or(&item.path);
+                                    ui.label(path.display().to_string());
+                                    ui.label(format_size(item.size));
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+
+        egui::Window::new(t!("trash"))
+            .open(show_trash)
+            .show(ctx, |ui| {
+                let trash = Trash::new(song_folder);
+                ui.horizontal(|ui| {
+                    ui.label(t!("trash_retention"));
+                    ui.add(
+                        egui::DragValue::new(&mut settings.trash_retention_days)
+                            .clamp_range(0..=3650)
+                            .suffix(t!("days")),
+                    )
+                    .on_hover_text(t!("trash_retention_hint"));
+                    if ui.button(t!("empty_trash")).clicked() {
+                        trash.empty();
+                        *trash_entries = trash.entries();
+                    }
+                });
+                ui.separator();
+                let mut restore_entry = None;
+                let mut remove_entry = None;
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("trash_entries")
+                            .num_columns(4)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for entry in trash_entries.iter() {
+                                    ui.label(&entry.song_name)
+                                        .on_hover_text(entry.original_path.display().to_string());
+                                    ui.label(format_time(entry.deleted_time()));
+                                    if ui.button(t!("restore")).clicked() {
+                                        restore_entry = Some(entry.clone());
+                                    }
+                                    if ui.button(t!("delete_permanently")).clicked() {
+                                        remove_entry = Some(entry.clone());
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                if let Some(entry) = restore_entry {
+                    match trash.restore(&entry) {
+                        Ok(()) => *list_outdated = true,
+                        Err(error) => warn!("Restore song failed. {}", error),
+                    }
+                    *trash_entries = trash.entries();
+                }
+                if let Some(entry) = remove_entry {
+                    if let Err(error) = trash.remove(&entry) {
+                        warn!("Delete song from trash failed. {}", error);
+                    }
+                    *trash_entries = trash.entries();
+                }
+            });
+
+        if let Some(plan) = change_plan {
+            let mut open = true;
+            let mut apply = false;
+            egui::Window::new(t!("review_changes"))
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    let applied = plan.applied().count();
+                    ui.label(t!(
+                        "review_summary",
+                        applied = &applied.to_string(),
+                        skipped = &(plan.changes.len() - applied).to_string()
+                    ));
+                    ui.separator();
+                    let mut suffix_change = None;
+                    egui::ScrollArea::vertical()
+                        .max_height(400.0)
+                        .show(ui, |ui| {
+                            egui::Grid::new("change_plan")
+                                .num_columns(5)
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    for (index, change) in plan.changes.iter_mut().enumerate() {
+                                        let source = &change.song.song_folder_path;
+                                        ui.label(match change.action {
+                                            Action::Delete => t!("delete"),
+                                            Action::Rename => t!("rename"),
+                                        });
+                                        ui.label(folder_name(source))
+                                            .on_hover_text(source.display().to_string());
+                                        match change.final_destination() {
+                                            Some(destination) => {
+                                                ui.label(format!("→ {}", folder_name(destination)))
+                                                    .on_hover_text(
+                                                        destination.display().to_string(),
+                                                    );
+                                            }
+                                            None => {
+                                                ui.label(match change.action {
+                                                    Action::Delete => t!("to_trash"),
+                                                    Action::Rename => String::new(),
+                                                });
+                                            }
+                                        }
+                                        match change.conflict {
+                                            Some(conflict) => {
+                                                ui.colored_label(
+                                                    ui.visuals().warn_fg_color,
+                                                    conflict_label(conflict),
+                                                );
+                                                ui.horizontal(|ui| {
+                                                    if conflict.can_suffix()
+                                                        && ui
+                                                            .selectable_label(
+                                                                matches!(
+                                                                    change.resolution,
+                                                                    Resolution::Suffix(_)
+                                                                ),
+                                                                t!("add_suffix"),
+                                                            )
+                                                            .clicked()
+                                                    {
+                                                        suffix_change = Some(index);
+                                                    }
+                                                    ui.selectable_value(
+                                                        &mut change.resolution,
+                                                        Resolution::Skip,
+                                                        t!("skip"),
+                                                    );
+                                                });
+                                            }
+                                            None => {
+                                                ui.label(t!("no_conflict"));
+                                                ui.label("");
+                                            }
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                    if let Some(index) = suffix_change {
+                        plan.suffix(index);
+                    }
+                    ui.separator();
+                    if ui.button(t!("apply_changes")).clicked() {
+                        apply = true;
+                    }
+                });
+            if apply {
+                let results = apply_changes(song_folder, plan);
+                // Failed changes and the ones skipped in review stay pending so that they can be
+                // retried.
+                let kept: HashSet<&Song> = results
+                    .iter()
+                    .filter(|result| result.outcome.is_err())
+                    .map(|result| &result.song)
+                    .chain(
+                        plan.changes
+                            .iter()
+                            .filter(|change| change.resolution == Resolution::Skip)
+                            .map(|change| &change.song),
+                    )
+                    .collect();
+                pending_changes.retain(|song, _| kept.contains(song));
+                *commit_results = Some(results);
+                *current_song = None;
+                *list_outdated = true;
+            }
+            if apply || !open {
+                *change_plan = None;
+            }
+        }
+
+        if let Some(results) = commit_results {
+            let mut open = true;
+            egui::Window::new(t!("commit_results"))
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    let failed: Vec<&ChangeResult> = results
+                        .iter()
+                        .filter(|result| result.outcome.is_err())
+                        .collect();
+                    ui.label(t!(
+                        "commit_summary",
+                        succeeded = &(results.len() - failed.len()).to_string(),
+                        failed = &failed.len().to_string()
+                    ));
+                    if !failed.is_empty() {
+                        ui.label(t!("failed_changes_kept"));
+                        ui.separator();
+                        egui::ScrollArea::vertical()
+                            .max_height(400.0)
+                            .show(ui, |ui| {
+                                egui::Grid::new("commit_failures")
+                                    .num_columns(3)
+                                    .striped(true)
+                                    .show(ui, |ui| {
+                                        for result in failed {
+                                            if let Err(error) = &result.outcome {
+                                                ui.label(match result.action {
+                                                    Action::Delete => t!("delete"),
+                                                    Action::Rename => t!("rename"),
+                                                });
+                                                ui.label(folder_name(
+                                                    &result.song.song_folder_path,
+                                                ))
+                                                .on_hover_text(
+                                                    result
+                                                        .song
+                                                        .song_folder_path
+                                                        .display()
+                                                        .to_string(),
+                                                );
+                                                ui.label(format!("{:?}", error.kind))
+                                                    .on_hover_text(&error.message);
+                                                ui.end_row();
+                                            }
+                                        }
+                                    });
+                            });
+                    }
+                });
+            if !open {
+                *commit_results = None;
+            }
+        }
+
+        if let Some(errors) = undo_errors {
+            let mut open = true;
+            egui::Window::new(t!("undo_results"))
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.label(t!(
+                        "undo_failed_summary",
+                        failed = &errors.len().to_string()
+                    ));
+                    ui.label(t!("failed_undo_kept"));
+                    ui.separator();
+                    egui::ScrollArea::vertical()
+                        .max_height(400.0)
+                        .show(ui, |ui| {
+                            for error in errors.iter() {
+                                ui.label(error);
+                            }
+                        });
+                });
+            if !open {
+                *undo_errors = None;
+            }
+        }
+
+        egui::Window::new(t!("invalid_folders_title"))
+            .open(show_invalid)
+            .show(ctx, |ui| {
+                let mut invalid_folders: Vec<(&PathBuf, &InvalidReason)> =
+                    invalid_path.iter().collect();
+                invalid_folders.sort_by(|a, b| a.0.cmp(b.0));
+                let mut quarantined = Vec::new();
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("invalid_folders")
+                            .num_columns(5)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for (path, reason) in invalid_folders {
+                                    ui.label(folder_name(path))
+                                        .on_hover_text(path.display().to_string());
+                                    ui.label(invalid_reason_label(reason));
+                                    if ui.button(t!("open")).clicked() {
+                                        let folder = match reason {
+                                            InvalidReason::NotADirectory => {
+                                                path.parent().unwrap_or(path)
+                                            }
+                                            _ => path,
+                                        };
+                                        if let Err(error) = open_in_file_manager(folder) {
+                                            warn!("Open folder failed. {}", error);
+                                        }
+                                    }
+                                    if ui.button(t!("quarantine")).clicked() {
+                                        match quarantine(path) {
+                                            Ok(_) => quarantined.push(path.clone()),
+                                            Err(error) => warn!("Quarantine failed. {}", error),
+                                        }
+                                    }
+                                    if ui.button(t!("queue_delete")).clicked() {
+                                        pending_changes
+                                            .insert(Song::placeholder(path), Action::Delete);
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                        let songs_with_problems: Vec<&Song> = song_list
+                            .iter()
+                            .filter(|song| !song.problems.is_empty())
+                            .collect();
+                        if !songs_with_problems.is_empty() {
+                            ui.separator();
+                            ui.label(t!("songs_with_problems"));
+                            egui::Grid::new("songs_with_problems")
+                                .num_columns(3)
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    for song in songs_with_problems {
+                                        if ui
+                                            .link(&song.song_name)
+                                            .on_hover_text(
+                                                song.song_folder_path.display().to_string(),
+                                            )
+                                            .clicked()
+                                        {
+                                            *current_song = Some(song.clone());
+                                        }
+                                        ui.label(
+                                            song.problems
+                                                .iter()
+                                                .map(invalid_reason_label)
+                                                .collect::<Vec<String>>()
+                                                .join("\n"),
+                                        );
+                                        if ui.button(t!("open")).clicked() {
+                                            if let Err(error) =
+                                                open_in_file_manager(&song.song_folder_path)
+                                            {
+                                                warn!("Open folder failed. {}", error);
+                                            }
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                        }
+                    });
+                for path in quarantined {
+                    invalid_path.remove(&path);
+                }
+            });
+
+        egui::SidePanel::left("song_list_panel")
+            .resizable(true)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                ui.heading(t!("song_list_title"));
+                let total_duration: Duration =
+                    song_list.iter().filter_map(|song| song.duration).sum();
+                ui.label(t!(
+                    "library_total",
+                    count = &song_list.len().to_string(),
+                    duration = &format_duration(total_duration)
+                ));
+
+                if let Some(task) = scan_task {
+                    ui.separator();
+                    let progress = &task.progress;
+                    let folders_total = progress.folders_total.load(Ordering::Relaxed);
+                    let folders_scanned = progress.folders_scanned.load(Ordering::Relaxed);
+                    let hashes_total = progress.hashes_total.load(Ordering::Relaxed);
+                    let hashes_resolved = progress.hashes_resolved.load(Ordering::Relaxed);
+                    let fraction = if folders_total == 0 {
+                        0.0
+                    } else {
+                        folders_scanned as f32 / folders_total as f32
+                    };
+                    ui.add(egui::ProgressBar::new(fraction).text(t!(
+                        "scan_folders_progress",
+                        scanned = &folders_scanned.to_string(),
+                        total = &folders_total.to_string()
+                    )));
+                    if hashes_total != 0 {
+                        ui.add(
+                            egui::ProgressBar::new(hashes_resolved as f32 / hashes_total as f32)
+                                .text(t!(
+                                    "scan_hashes_progress",
+                                    resolved = &hashes_resolved.to_string(),
+                                    total = &hashes_total.to_string()
+                                )),
+                        );
+                    }
+                    ui.horizontal(|ui| {
+                        if progress.is_cancelled() {
+                            ui.spinner();
+                            ui.label(t!("scan_cancelling"));
+                        } else if ui.button(t!("cancel")).clicked() {
+                            task.cancel();
+                        }
+                    });
+                } else if !skipped_path.is_empty() {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let paths: Vec<String> = skipped_path
+                            .iter()
+                            .map(|path| path.display().to_string())
+                            .collect();
+                        ui.label(t!("scan_skipped", count = &skipped_path.len().to_string()))
+                            .on_hover_text(paths.join("\n"));
+                        if ui.button(t!("refresh")).clicked() {
+                            *list_outdated = true;
+                        }
+                    });
+                }
+                if scan_task.is_none() {
+                    if let Some(error) = lookup_error {
+                        ui.horizontal(|ui| {
+                            ui.label(t!("lookup_failed")).on_hover_text(error.as_str());
+                            if ui.button(t!("refresh")).clicked() {
+                                *list_outdated = true;
+                            }
+                        });
+                    }
+                }
+
+                ui.separator();
+                ui.menu_button(t!("columns"), |ui| {
+                    for column in SongColumn::ALL {
+                        let mut visible = settings.visible_columns.contains(&column);
+                        // The name column is the one to click on, so it can't be hidden.
+                        let checkbox = ui.add_enabled(
+                            column != SongColumn::Name,
+                            egui::Checkbox::new(&mut visible, column.label()),
+                        );
+                        if checkbox.changed() {
+                            let mut visible_columns = settings.visible_columns.clone();
+                            if visible {
+                                visible_columns.push(column);
+                            } else {
+                                visible_columns.retain(|other| *other != column);
+                            }
+                            // Keep the columns in their usual order.
+                            settings.visible_columns = SongColumn::ALL
+                                .into_iter()
+                                .filter(|column| visible_columns.contains(column))
+                                .collect();
+                        }
+                    }
+                });
+                filter_bar(ui, song_list, song_filter, search_texts);
+                ui.separator();
+                ui.horizontal_wrapped(|ui| {
+                    ui.label(t!(
+                        "selected_count",
+                        count = &selection.paths.len().to_string()
+                    ));
+                    if ui.button(t!("select_all_matching")).clicked() {
+                        let matcher = song_filter.matcher(search_texts);
+                        for song in song_list.iter().filter(|song| matcher.matches(song)) {
+                            selection.paths.insert(song.song_folder_path.clone());
+                        }
+                    }
+                    if ui.button(t!("clear_selection")).clicked() {
+                        selection.clear();
+                    }
+                });
+                if !selection.paths.is_empty() {
+                    ui.horizontal_wrapped(|ui| {
+                        if ui.button(t!("queue_delete")).clicked() {
+                            for song in selection.songs(song_list) {
+                                pending_changes.insert(song.clone(), Action::Delete);
+                            }
+                        }
+                        if ui.button(t!("queue_rename")).clicked() {
+                            for song in selection.songs(song_list) {
+                                pending_changes.insert(song.clone(), Action::Rename);
+                            }
+                        }
+                        if ui.button(t!("add_to_playlist")).clicked() {
+                            for song in selection.songs(song_list) {
+                                if !playlist_songs.contains(song) {
+                                    playlist_songs.push(song.clone());
+                                }
+                            }
+                        }
+                    });
+                }
+                ui.separator();
+                song_table(
+                    ui,
+                    song_list,
+                    song_filter,
+                    search_texts,
+                    settings,
+                    selection,
+                    current_song,
+                );
+            });
 
         egui::CentralPanel::default().show(ctx, |ui| {
             if let Some(current_song) = current_song {
@@ -146,6 +1703,44 @@ impl eframe::App for ManagerApp {
                 ui.label(t!("song_author", author = &current_song.song_author_name));
                 ui.label(t!("level_author", author = &current_song.level_author_name));
                 ui.label(t!("bpm", bpm = &current_song.beats_per_minute.to_string()));
+                ui.label(t!(
+                    "duration",
+                    duration = &current_song
+                        .duration
+                        .map(format_duration)
+                        .unwrap_or_default()
+                ));
+                ui.label(t!(
+                    "info_dat_version",
+                    version = &current_song.info_dat_version
+                ));
+                ui.label(t!(
+                    "song_time_offset",
+                    offset = &current_song.song_time_offset.to_string()
+                ));
+                ui.label(t!(
+                    "preview",
+                    start = &current_song.preview_start_time.to_string(),
+                    duration = &current_song.preview_duration.to_string()
+                ));
+                if current_song.shuffle != 0.0 {
+                    ui.label(t!(
+                        "shuffle",
+                        shuffle = &current_song.shuffle.to_string(),
+                        period = &current_song.shuffle_period.to_string()
+                    ));
+                }
+                ui.label(t!("environment", name = &current_song.environment_name));
+                if !current_song.all_directions_environment_name.is_empty() {
+                    ui.label(t!(
+                        "all_directions_environment",
+                        name = &current_song.all_directions_environment_name
+                    ));
+                }
+                for contributor in &current_song.contributors {
+                    ui.label(format!("{}: {}", contributor.role, contributor.name))
+                        .on_hover_text(&contributor.icon_path);
+                }
                 ui.separator();
                 ui.horizontal(|ui| {
                     ui.label(t!("level_id", id = &current_song.level_id));
@@ -173,22 +1768,64 @@ impl eframe::App for ManagerApp {
                 ui.separator();
                 for difficulty_beatmap_set in &current_song.difficulty_beatmap_sets {
                     ui.horizontal_wrapped(|ui| {
-                        ui.collapsing(
-                            difficulty_beatmap_set.beatmap_characteristic_name.as_str(),
-                            |ui| {
-                                for difficulty_beatmap in
-                                    &difficulty_beatmap_set.difficulty_beatmaps
-                                {
-                                    ui.horizontal_wrapped(|ui| {
-                                        ui.spacing_mut().item_spacing.x = 0.0;
-                                        ui.label(&difficulty_beatmap.difficulty).on_hover_text(t!(
-                                            "difficulty_rank",
-                                            rank = &difficulty_beatmap.difficulty_rank.to_string()
-                                        ));
-                                    });
+                        let characteristic = &difficulty_beatmap_set.beatmap_characteristic_name;
+                        let collapsing = ui.collapsing(characteristic.as_str(), |ui| {
+                            for difficulty_beatmap in &difficulty_beatmap_set.difficulty_beatmaps {
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.spacing_mut().item_spacing.x = 0.0;
+                                    let custom_data = &difficulty_beatmap.custom_data;
+                                    let name = match &custom_data.difficulty_label {
+                                        Some(label) => {
+                                            format!("{} ({})", difficulty_beatmap.difficulty, label)
+                                        }
+                                        None => difficulty_beatmap.difficulty.clone(),
+                                    };
+                                    ui.label(name).on_hover_text(t!(
+                                        "difficulty_rank",
+                                        rank = &difficulty_beatmap.difficulty_rank.to_string()
+                                    ));
+                                });
+                                let stats = &difficulty_beatmap.stats;
+                                ui.label(t!(
+                                    "beatmap_objects",
+                                    notes = &stats.notes.to_string(),
+                                    bombs = &stats.bombs.to_string(),
+                                    walls = &stats.walls.to_string(),
+                                    arcs = &stats.arcs.to_string(),
+                                    chains = &stats.chains.to_string(),
+                                    events = &stats.events.to_string()
+                                ));
+                                ui.label(t!(
+                                    "beatmap_speed",
+                                    nps = &format!(
+                                        "{:.2}",
+                                        stats.notes_per_second(
+                                            current_song.beats_per_minute,
+                                            current_song.duration
+                                        )
+                                    ),
+                                    njs = &difficulty_beatmap.note_jump_movement_speed.to_string(),
+                                    offset =
+                                        &difficulty_beatmap.note_jump_start_beat_offset.to_string()
+                                ));
+                                let custom_data = &difficulty_beatmap.custom_data;
+                                for (key, values) in [
+                                    ("requirements", &custom_data.requirements),
+                                    ("suggestions", &custom_data.suggestions),
+                                    ("warnings", &custom_data.warnings),
+                                    ("information", &custom_data.information),
+                                ] {
+                                    if !values.is_empty() {
+                                        ui.label(t!(key, list = &values.join(", ")));
+                                    }
                                 }
-                            },
-                        );
+                            }
+                        });
+                        if let BeatmapCharacteristic::Custom(_) = characteristic {
+                            collapsing
+                                .header_response
+                                .on_hover_text(t!("custom_characteristic"));
+                        }
                     });
                     ui.separator();
                 }
@@ -203,14 +1840,30 @@ impl eframe::App for ManagerApp {
                 ui.heading(t!("pending_change_list_title"));
                 ui.separator();
                 if ui.button(t!("commit_changes")).clicked() && !pending_changes.is_empty() {
-                    apply_changes(pending_changes);
-                    *pending_changes = HashMap::new();
-                    *current_song = None;
-                    *list_outdated = true;
+                    *change_plan = Some(ChangePlan::new(pending_changes, &settings.naming));
                 }
                 if ui.button(t!("reset_changes")).clicked() {
                     *pending_changes = HashMap::new();
                 }
+                if let Some(batch) = last_commit {
+                    let undo_button = ui.button(t!("undo_last_commit")).on_hover_text(t!(
+                        "undo_last_commit_hint",
+                        count = &batch.pending().count().to_string(),
+                        time = &format_time(batch.committed_time())
+                    ));
+                    if undo_button.clicked() {
+                        let errors = match Journal::new(song_folder).undo_last() {
+                            Ok(errors) => errors,
+                            Err(error) => {
+                                warn!("Undo last commit failed. {}", error);
+                                vec![error.to_string()]
+                            }
+                        };
+                        *undo_errors = (!errors.is_empty()).then_some(errors);
+                        *current_song = None;
+                        *list_outdated = true;
+                    }
+                }
             });
             if !pending_changes.is_empty() {
                 let mut withdraw_list = HashMap::new();
@@ -239,9 +1892,11 @@ impl eframe::App for ManagerApp {
                                     });
                                 });
                                 row.col(|ui| {
-                                    ui.label(song.song_name.as_str()).on_hover_text(
-                                        song.song_folder_path.as_path().display().to_string(),
-                                    );
+                                    ui.label(song.song_name.as_str()).on_hover_text(format!(
+                                        "{}\n{}",
+                                        song.song_folder_path.as_path().display(),
+                                        format_size(song.folder_size)
+                                    ));
                                 });
                                 row.col(|ui| {
                                     if ui.button("-").clicked() {
@@ -264,7 +1919,7 @@ impl eframe::App for ManagerApp {
                 let rename_tip = format!(
                     "{}\n⬇\n{}",
                     song.song_folder_path.file_name().unwrap().to_str().unwrap(),
-                    song.get_canonical_name()
+                    song.get_canonical_name(&settings.naming)
                 );
                 ui.horizontal(|ui| {
                     if ui.button(t!("delete")).clicked() {
@@ -273,6 +1928,10 @@ impl eframe::App for ManagerApp {
                     if ui.button(t!("rename")).on_hover_text(rename_tip).clicked() {
                         pending_changes.insert(song.clone(), Action::Rename);
                     }
+                    if ui.button(t!("add_to_playlist")).clicked() && !playlist_songs.contains(song)
+                    {
+                        playlist_songs.push(song.clone());
+                    }
                 });
                 if let Some(stream_handle) = stream_handle {
                     ui.horizontal(|ui| {
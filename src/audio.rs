@@ -0,0 +1,162 @@
+use lewton::inside_ogg::OggStreamReader;
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    time::Duration,
+};
+
+/// Capture pattern at the start of every Ogg page.
+const OGG_CAPTURE_PATTERN: &[u8] = b"OggS";
+/// Size of the fixed part of an Ogg page header, the segment table follows it.
+const OGG_HEADER_SIZE: usize = 27;
+/// Granule position of a page on which no packet ends.
+const NO_GRANULE_POSITION: u64 = u64::MAX;
+/// How many bytes from the end of the file to look for the last page in.
+const LAST_PAGE_SEARCH_SIZE: u64 = 64 * 1024;
+
+/// Duration of an Ogg Vorbis (`.ogg`/`.egg`) file.
+///
+/// The sample rate comes from the vorbis identification header and the sample count from the
+/// granule position of the last Ogg page, so the audio doesn't need to be decoded. Vorbis has
+/// no pre-skip, the granule position of the last page is the number of samples.
+pub(crate) fn ogg_duration(path: &Path) -> Result<Duration, Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    let reader = OggStreamReader::new(BufReader::new(&file))?;
+    let sample_rate = reader.ident_hdr.audio_sample_rate;
+    let serial = reader.stream_serial();
+    let file_size = file.metadata()?.len();
+    let search_size = file_size.min(LAST_PAGE_SEARCH_SIZE);
+    file.seek(SeekFrom::Start(file_size - search_size))?;
+    let mut buffer = Vec::new();
+    file.take(search_size).read_to_end(&mut buffer)?;
+    let samples =
+        last_granule_position(&buffer, serial).ok_or("no ogg page found at the end of file")?;
+    Ok(Duration::from_secs_f64(
+        samples as f64 / f64::from(sample_rate),
+    ))
+}
+
+/// Granule position of the last complete page of the stream `serial` in `buffer`.
+///
+/// A capture pattern only counts as a page if the header is valid and the checksum matches, so
+/// that `OggS` showing up inside packet data is skipped.
+fn last_granule_position(buffer: &[u8], serial: u32) -> Option<u64> {
+    buffer
+        .windows(OGG_CAPTURE_PATTERN.len())
+        .enumerate()
+        .rev()
+        .filter(|(_, window)| *window == OGG_CAPTURE_PATTERN)
+        .filter_map(|(page_start, _)| parse_page(&buffer[page_start..]))
+        .find(|page| page.serial == serial && page.granule_position != NO_GRANULE_POSITION)
+        .map(|page| page.granule_position)
+}
+
+struct OggPage {
+    granule_position: u64,
+    serial: u32,
+}
+
+/// Parse the page at the start of `data`.
+///
+/// Header layout: capture pattern(4), version(1), header type(1), granule position(8),
+/// serial(4), sequence number(4), checksum(4), segment count(1), segment table.
+fn parse_page(data: &[u8]) -> Option<OggPage> {
+    let header = data.get(..OGG_HEADER_SIZE)?;
+    // Only version 0 exists and the header type uses the lowest three bits.
+    if header[4] != 0 || header[5] & !0x07 != 0 {
+        return None;
+    }
+    let segment_count = usize::from(header[26]);
+    let segment_table = data.get(OGG_HEADER_SIZE..OGG_HEADER_SIZE + segment_count)?;
+    let body_size: usize = segment_table.iter().map(|size| usize::from(*size)).sum();
+    let page = data.get(..OGG_HEADER_SIZE + segment_count + body_size)?;
+    let checksum = u32::from_le_bytes(header[22..26].try_into().ok()?);
+    let mut checked = page.to_vec();
+    checked[22..26].fill(0);
+    if ogg_crc(&checked) != checksum {
+        return None;
+    }
+    Some(OggPage {
+        granule_position: u64::from_le_bytes(header[6..14].try_into().ok()?),
+        serial: u32::from_le_bytes(header[14..18].try_into().ok()?),
+    })
+}
+
+/// CRC-32 used by Ogg pages: polynomial 0x04c11db7, no reflection, zero initial value.
+fn ogg_crc(data: &[u8]) -> u32 {
+    data.iter().fold(0, |mut crc: u32, byte| {
+        crc ^= u32::from(*byte) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(granule_position: u64, serial: u32, body: &[u8]) -> Vec<u8> {
+        let mut page = OGG_CAPTURE_PATTERN.to_vec();
+        page.extend([0, 0]);
+        page.extend(granule_position.to_le_bytes());
+        page.extend(serial.to_le_bytes());
+        page.extend(7u32.to_le_bytes());
+        page.extend([0; 4]);
+        page.push(1);
+        page.push(body.len() as u8);
+        page.extend(body);
+        let checksum = ogg_crc(&page);
+        page[22..26].copy_from_slice(&checksum.to_le_bytes());
+        page
+    }
+
+    #[test]
+    fn checksum_matches_ogg() {
+        assert_eq!(ogg_crc(&[61, 61, 33]), 0x9f85_8776);
+    }
+
+    #[test]
+    fn takes_granule_of_last_page() {
+        let mut data = page(1000, 1, b"first");
+        data.extend(page(44100, 1, b"last"));
+        assert_eq!(last_granule_position(&data, 1), Some(44100));
+    }
+
+    #[test]
+    fn skips_capture_pattern_inside_packet_data() {
+        let mut data = page(44100, 1, b"audio");
+        data.extend(b"OggS\0\0\xff\xff\xff\xff\xff\xff\xff\x00 garbage");
+        assert_eq!(last_granule_position(&data, 1), Some(44100));
+    }
+
+    #[test]
+    fn skips_pages_without_granule_position() {
+        let mut data = page(44100, 1, b"audio");
+        data.extend(page(NO_GRANULE_POSITION, 1, b"continued"));
+        assert_eq!(last_granule_position(&data, 1), Some(44100));
+    }
+
+    #[test]
+    fn skips_pages_of_other_streams_and_truncated_pages() {
+        let mut data = page(44100, 1, b"audio");
+        data.extend(page(88200, 2, b"other stream"));
+        let truncated = page(96000, 1, b"truncated");
+        data.extend(&truncated[..truncated.len() - 2]);
+        assert_eq!(last_granule_position(&data, 1), Some(44100));
+    }
+
+    #[test]
+    fn rejects_corrupted_page() {
+        let mut data = page(44100, 1, b"audio");
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        assert_eq!(last_granule_position(&data, 1), None);
+    }
+}
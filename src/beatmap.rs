@@ -0,0 +1,186 @@
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+use std::time::Duration;
+
+/// Object counts of a difficulty file.
+///
+/// Refer https://bsmg.wiki/mapping/map-format/beatmap.html
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BeatmapStats {
+    pub(crate) notes: usize,
+    pub(crate) bombs: usize,
+    pub(crate) walls: usize,
+    pub(crate) arcs: usize,
+    pub(crate) chains: usize,
+    pub(crate) events: usize,
+    /// Beat of the first and last note, used to estimate how long the map is played.
+    pub(crate) first_beat: f64,
+    pub(crate) last_beat: f64,
+}
+
+/// Every array of interest from the v2, v3 and v4 formats, only the ones of the actual format
+/// are present in a file.
+#[derive(Deserialize)]
+struct BeatmapFile {
+    #[serde(rename = "_notes", default)]
+    notes_v2: Vec<NoteV2>,
+    #[serde(rename = "_obstacles", default)]
+    obstacles_v2: Vec<IgnoredAny>,
+    #[serde(rename = "_sliders", default)]
+    sliders_v2: Vec<IgnoredAny>,
+    #[serde(rename = "_events", default)]
+    events_v2: Vec<IgnoredAny>,
+    #[serde(rename = "colorNotes", default)]
+    color_notes: Vec<Note>,
+    #[serde(rename = "bombNotes", default)]
+    bomb_notes: Vec<IgnoredAny>,
+    #[serde(rename = "obstacles", default)]
+    obstacles: Vec<IgnoredAny>,
+    #[serde(rename = "sliders", default)]
+    sliders_v3: Vec<IgnoredAny>,
+    #[serde(rename = "burstSliders", default)]
+    burst_sliders_v3: Vec<IgnoredAny>,
+    #[serde(rename = "arcs", default)]
+    arcs_v4: Vec<IgnoredAny>,
+    #[serde(rename = "chains", default)]
+    chains_v4: Vec<IgnoredAny>,
+    #[serde(rename = "basicBeatmapEvents", default)]
+    events_v3: Vec<IgnoredAny>,
+    #[serde(rename = "basicEvents", default)]
+    events_v4: Vec<IgnoredAny>,
+}
+
+#[derive(Deserialize)]
+struct NoteV2 {
+    #[serde(rename = "_time", default)]
+    time: f64,
+    #[serde(rename = "_type", default)]
+    note_type: u64,
+}
+
+#[derive(Deserialize)]
+struct Note {
+    #[serde(rename = "b", default)]
+    beat: f64,
+}
+
+/// Note type of bombs in v2 `_notes`, other types are the red and blue notes.
+const NOTE_TYPE_BOMB: u64 = 3;
+
+impl BeatmapStats {
+    pub(crate) fn from_data(data: &[u8]) -> Result<Self, serde_json::Error> {
+        let file: BeatmapFile = serde_json::from_slice(data)?;
+        let mut beats = Vec::new();
+        let mut bombs = file.bomb_notes.len();
+        for note in &file.notes_v2 {
+            if note.note_type == NOTE_TYPE_BOMB {
+                bombs += 1;
+            } else {
+                beats.push(note.time);
+            }
+        }
+        beats.extend(file.color_notes.iter().map(|note| note.beat));
+        Ok(BeatmapStats {
+            notes: beats.len(),
+            bombs,
+            walls: file.obstacles_v2.len() + file.obstacles.len(),
+            arcs: file.sliders_v2.len() + file.sliders_v3.len() + file.arcs_v4.len(),
+            chains: file.burst_sliders_v3.len() + file.chains_v4.len(),
+            events: file.events_v2.len() + file.events_v3.len() + file.events_v4.len(),
+            first_beat: beats.iter().copied().reduce(f64::min).unwrap_or_default(),
+            last_beat: beats.iter().copied().fold(0.0, f64::max),
+        })
+    }
+
+    /// Notes per second over the song duration, or between the first and the last note when
+    /// the duration is unknown.
+    pub(crate) fn notes_per_second(
+        &self,
+        beats_per_minute: f64,
+        duration: Option<Duration>,
+    ) -> f64 {
+        let seconds = match duration {
+            Some(duration) => duration.as_secs_f64(),
+            None => (self.last_beat - self.first_beat) * 60.0 / beats_per_minute,
+        };
+        if self.notes == 0 || seconds <= 0.0 || !seconds.is_finite() {
+            return 0.0;
+        }
+        self.notes as f64 / seconds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_v2_objects() {
+        let data = br#"{
+            "_notes": [
+                {"_time": 4, "_type": 0},
+                {"_time": 12, "_type": 1},
+                {"_time": 8, "_type": 3}
+            ],
+            "_obstacles": [{}],
+            "_sliders": [{}, {}],
+            "_events": [{}, {}, {}]
+        }"#;
+        let stats = BeatmapStats::from_data(data).unwrap();
+        assert_eq!(
+            stats,
+            BeatmapStats {
+                notes: 2,
+                bombs: 1,
+                walls: 1,
+                arcs: 2,
+                chains: 0,
+                events: 3,
+                first_beat: 4.0,
+                last_beat: 12.0,
+            }
+        );
+    }
+
+    #[test]
+    fn counts_v3_and_v4_objects() {
+        let v3 = br#"{
+            "colorNotes": [{"b": 2}, {"b": 6}],
+            "bombNotes": [{}],
+            "obstacles": [{}, {}],
+            "sliders": [{}],
+            "burstSliders": [{}, {}],
+            "basicBeatmapEvents": [{}]
+        }"#;
+        let stats = BeatmapStats::from_data(v3).unwrap();
+        assert_eq!((stats.notes, stats.bombs, stats.walls), (2, 1, 2));
+        assert_eq!((stats.arcs, stats.chains, stats.events), (1, 2, 1));
+        assert_eq!((stats.first_beat, stats.last_beat), (2.0, 6.0));
+
+        let v4 =
+            br#"{"colorNotes": [{"b": 1}], "arcs": [{}], "chains": [{}], "basicEvents": [{}]}"#;
+        let stats = BeatmapStats::from_data(v4).unwrap();
+        assert_eq!(
+            (stats.notes, stats.arcs, stats.chains, stats.events),
+            (1, 1, 1, 1)
+        );
+    }
+
+    #[test]
+    fn notes_per_second() {
+        let stats = BeatmapStats {
+            notes: 120,
+            first_beat: 0.0,
+            last_beat: 120.0,
+            ..BeatmapStats::default()
+        };
+        assert_eq!(
+            stats.notes_per_second(120.0, Some(Duration::from_secs(30))),
+            4.0
+        );
+        // 120 beats at 120 BPM are played in a minute.
+        assert_eq!(stats.notes_per_second(120.0, None), 2.0);
+        assert_eq!(stats.notes_per_second(0.0, None), 0.0);
+        assert_eq!(BeatmapStats::default().notes_per_second(120.0, None), 0.0);
+    }
+}
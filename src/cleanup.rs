@@ -0,0 +1,204 @@
+use log::warn;
+use std::{
+    collections::HashSet,
+    fs::read_dir,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    thread,
+};
+
+use crate::{Song, DATA_DIR_NAME};
+
+/// Files created by the operating system's file browsers.
+const OS_JUNK: &[&str] = &["thumbs.db", "desktop.ini", ".ds_store", "ehthumbs.db"];
+/// Files mods read from a song folder without info.dat naming them.
+const KNOWN_EXTRA_FILES: &[&str] = &["bpminfo.dat", "cinema-video.json"];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CleanupKind {
+    /// A file directly in the song folder, where only song folders belong.
+    StrayFile,
+    EmptyFolder,
+    /// A file in a song folder that its info.dat doesn't name.
+    UnreferencedFile,
+    OsJunk,
+}
+
+pub(crate) struct CleanupItem {
+    pub(crate) path: PathBuf,
+    pub(crate) kind: CleanupKind,
+    /// Size in bytes.
+    pub(crate) size: u64,
+    /// Whether the item gets queued for deletion.
+    pub(crate) selected: bool,
+}
+
+impl CleanupItem {
+    fn new(path: PathBuf, kind: CleanupKind, size: u64) -> Self {
+        CleanupItem {
+            path,
+            kind,
+            size,
+            // Unreferenced files may still be used by a mod and stray files may have been put
+            // there on purpose, so they are opt-in.
+            selected: !matches!(kind, CleanupKind::UnreferencedFile | CleanupKind::StrayFile),
+        }
+    }
+}
+
+fn is_os_junk(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| OS_JUNK.contains(&name.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Paths relative to the song folder that info.dat refers to, in lowercase as Windows file
+/// names are case-insensitive.
+fn referenced_files(song: &Song) -> HashSet<String> {
+    let mut files: HashSet<String> = [
+        "info.dat",
+        song.song_filename.as_str(),
+        song.audio_data_filename.as_str(),
+        song.cover_image_filename.as_str(),
+    ]
+    .into_iter()
+    .chain(KNOWN_EXTRA_FILES.iter().copied())
+    .chain(
+        song.contributors
+            .iter()
+            .map(|contributor| contributor.icon_path.as_str()),
+    )
+    .map(str::to_string)
+    .collect();
+    for set in &song.difficulty_beatmap_sets {
+        for beatmap in &set.difficulty_beatmaps {
+            files.insert(beatmap.beatmap_filename.clone());
+            files.insert(beatmap.lightshow_filename.clone());
+        }
+    }
+    files
+        .into_iter()
+        .filter(|file| !file.is_empty())
+        .map(|file| file.replace('\\', "/").to_lowercase())
+        .collect()
+}
+
+/// Collect the files under `folder` that are not in `referenced`.
+fn find_unreferenced(
+    song_folder: &Path,
+    folder: &Path,
+    referenced: &HashSet<String>,
+    items: &mut Vec<CleanupItem>,
+) {
+    let entries = match read_dir(folder) {
+        Ok(entries) => entries,
+        Err(error) => {
+            warn!("Read file list of {} failed. {}", folder.display(), error);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.is_dir() {
+            find_unreferenced(song_folder, &path, referenced, items);
+            continue;
+        }
+        if is_os_junk(&path) {
+            items.push(CleanupItem::new(path, CleanupKind::OsJunk, metadata.len()));
+            continue;
+        }
+        let relative = match path.strip_prefix(song_folder) {
+            Ok(relative) => relative.to_string_lossy().replace('\\', "/").to_lowercase(),
+            Err(_) => continue,
+        };
+        if !referenced.contains(&relative) {
+            items.push(CleanupItem::new(
+                path,
+                CleanupKind::UnreferencedFile,
+                metadata.len(),
+            ));
+        }
+    }
+}
+
+/// Everything in the song folder `song_path` that can be cleaned up, given the songs loaded
+/// from it.
+fn find_cleanup(song_path: &Path, song_list: &[Song]) -> Vec<CleanupItem> {
+    let mut items = Vec::new();
+    let entries = match read_dir(song_path) {
+        Ok(entries) => entries,
+        Err(error) => {
+            warn!(
+                "Read file list of {} failed. {}",
+                song_path.display(),
+                error
+            );
+            return items;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.ends_with(DATA_DIR_NAME) || path.ends_with("id.cache") {
+            continue;
+        }
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.is_dir() {
+            let is_empty = read_dir(&path)
+                .map(|mut entries| entries.next().is_none())
+                .unwrap_or(false);
+            if is_empty {
+                items.push(CleanupItem::new(path, CleanupKind::EmptyFolder, 0));
+            }
+        } else if is_os_junk(&path) {
+            items.push(CleanupItem::new(path, CleanupKind::OsJunk, metadata.len()));
+        } else {
+            items.push(CleanupItem::new(
+                path,
+                CleanupKind::StrayFile,
+                metadata.len(),
+            ));
+        }
+    }
+    for song in song_list {
+        let referenced = referenced_files(song);
+        find_unreferenced(
+            &song.song_folder_path,
+            &song.song_folder_path,
+            &referenced,
+            &mut items,
+        );
+    }
+    items
+}
+
+/// A search for cleanup items running on a background thread, as it stats every file of the
+/// library.
+pub(crate) struct CleanupTask {
+    receiver: Receiver<Vec<CleanupItem>>,
+}
+
+impl CleanupTask {
+    pub(crate) fn start(song_path: PathBuf, song_list: Vec<Song>) -> Self {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let _ = sender.send(find_cleanup(&song_path, &song_list));
+        });
+        CleanupTask { receiver }
+    }
+
+    /// The items found, once the search is done.
+    pub(crate) fn try_result(&self) -> Option<Vec<CleanupItem>> {
+        match self.receiver.try_recv() {
+            Ok(items) => Some(items),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Vec::new()),
+        }
+    }
+}
@@ -0,0 +1,435 @@
+//! Headless mode for running library maintenance from scripts.
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::{collections::HashMap, path::PathBuf, process::ExitCode};
+
+use crate::{
+    apply_changes, default_parallelism,
+    duplicate::{find_duplicates, songs_to_delete, KeepRule},
+    naming::NamingTemplate,
+    plan::{ChangePlan, Resolution},
+    scan_songs, Action, Playlist, ScanOptions, Snapshot, SnapshotDiff, Song, DEFAULT_ID,
+};
+
+#[derive(Parser)]
+#[command(version, about = "Manage the custom songs of Beat Saber")]
+pub struct Cli {
+    /// Run without a window, the GUI starts when this is left out.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Scan a song folder and print a summary
+    Scan(LibraryArgs),
+    /// List the songs of a song folder
+    List {
+        #[command(flatten)]
+        library: LibraryArgs,
+        /// Print a JSON array instead of tab separated lines
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rename song folders following a naming template
+    Rename {
+        #[command(flatten)]
+        library: LibraryArgs,
+        /// Rename every song of the song folder
+        #[arg(long)]
+        all: bool,
+        /// Song folders to rename
+        folders: Vec<PathBuf>,
+        #[command(flatten)]
+        naming: NamingArgs,
+        /// Only print what would be renamed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Move duplicate songs to the trash
+    Dedupe {
+        #[command(flatten)]
+        library: LibraryArgs,
+        /// Which song of a group of duplicates to keep
+        #[arg(long, value_enum, default_value = "newest")]
+        keep: Keep,
+        /// Naming used by `--keep canonical-name`
+        #[command(flatten)]
+        naming: NamingArgs,
+        /// Only print what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export every song of the song folder as a .bplist playlist
+    ExportPlaylist {
+        #[command(flatten)]
+        library: LibraryArgs,
+        /// Path of the playlist file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(long)]
+        title: String,
+        #[arg(long, default_value = "")]
+        author: String,
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+    /// Check the song folder for invalid entries, exits with 1 if there are any
+    Validate(LibraryArgs),
+    /// Save every song of the song folder as a JSON snapshot
+    Snapshot {
+        #[command(flatten)]
+        library: LibraryArgs,
+        /// Path of the snapshot file to write, printed when left out
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Compare two snapshots and print the songs that were added, removed, moved or changed
+    Diff {
+        /// The older snapshot
+        old: PathBuf,
+        /// The newer snapshot
+        new: PathBuf,
+    },
+}
+
+#[derive(Args)]
+pub struct LibraryArgs {
+    /// Song folder, usually `Beat Saber_Data/CustomLevels`
+    pub song_folder: PathBuf,
+    /// Number of song folders loaded at the same time
+    #[arg(long)]
+    pub threads: Option<usize>,
+    /// Parse every song folder again instead of reusing the library index
+    #[arg(long)]
+    pub full_rescan: bool,
+    /// Don't write the library index, which is otherwise saved to `.bs_song_manager/index.json`
+    /// in the song folder after every scan
+    #[arg(long)]
+    pub no_index: bool,
+}
+
+#[derive(Args)]
+pub struct NamingArgs {
+    /// Naming template, e.g. "{id} ({name} - {mapper})"
+    #[arg(long)]
+    pub template: Option<String>,
+    /// Keep Unicode characters instead of transliterating them
+    #[arg(long)]
+    pub keep_unicode: bool,
+}
+
+impl NamingArgs {
+    fn naming(&self) -> NamingTemplate {
+        let mut naming = NamingTemplate::default();
+        if let Some(template) = &self.template {
+            naming.template = template.clone();
+        }
+        naming.keep_unicode = self.keep_unicode;
+        naming
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Keep {
+    Newest,
+    CanonicalName,
+}
+
+impl LibraryArgs {
+    /// Scan the song folder, a failed level id lookup is reported but doesn't stop the command.
+    fn scan(&self) -> (Vec<Song>, Vec<(PathBuf, crate::InvalidReason)>) {
+        let options = ScanOptions {
+            parallelism: self.threads.unwrap_or_else(default_parallelism),
+            full_rescan: self.full_rescan,
+            update_index: !self.no_index,
+        };
+        let result = scan_songs(&self.song_folder, &options);
+        if let Some(error) = result.lookup_error {
+            eprintln!(
+                "Warning: some level ids couldn't be looked up on BeatSaver: {}",
+                error
+            );
+        }
+        (result.songs, result.invalid)
+    }
+}
+
+fn song_json(song: &Song) -> serde_json::Value {
+    serde_json::json!({
+        "path": song.song_folder_path,
+        "levelId": song.level_id,
+        "hash": song.level_hash,
+        "songName": song.song_name,
+        "songSubName": song.song_sub_name,
+        "songAuthorName": song.song_author_name,
+        "levelAuthorName": song.level_author_name,
+        "bpm": song.beats_per_minute,
+        "duration": song.duration.map(|duration| duration.as_secs_f64()),
+        "folderSize": song.folder_size,
+    })
+}
+
+/// Apply `plan` and print the outcome of every change, returns whether all of them succeeded.
+fn apply_plan(song_folder: &std::path::Path, plan: &ChangePlan) -> bool {
+    let mut success = true;
+    for result in apply_changes(song_folder, plan) {
+        match result.outcome {
+            Ok(destination) => println!(
+                "{} {} -> {}",
+                result.action.as_str(),
+                result.song.song_folder_path.display(),
+                destination.display()
+            ),
+            Err(error) => {
+                success = false;
+                eprintln!(
+                    "Failed to {} {}: {:?} {}",
+                    result.action.as_str(),
+                    result.song.song_folder_path.display(),
+                    error.kind,
+                    error.message
+                );
+            }
+        }
+    }
+    success
+}
+
+/// Run a headless command.
+pub fn run(command: Command) -> ExitCode {
+    match command {
+        Command::Scan(library) => {
+            let (songs, invalid) = library.scan();
+            let unknown = songs
+                .iter()
+                .filter(|song| song.level_id == DEFAULT_ID)
+                .count();
+            println!("Songs: {}", songs.len());
+            println!("Without BeatSaver ID: {}", unknown);
+            println!("Invalid entries: {}", invalid.len());
+            ExitCode::SUCCESS
+        }
+        Command::List { library, json } => {
+            let (songs, _) = library.scan();
+            if json {
+                let list: Vec<serde_json::Value> = songs.iter().map(song_json).collect();
+                match serde_json::to_string_pretty(&list) {
+                    Ok(text) => println!("{}", text),
+                    Err(error) => {
+                        eprintln!("Serialize song list failed. {}", error);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            } else {
+                for song in &songs {
+                    println!(
+                        "{}\t{}\t{}\t{}\t{}",
+                        song.level_id,
+                        song.level_hash,
+                        song.song_name,
+                        song.level_author_name,
+                        song.song_folder_path.display()
+                    );
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        Command::Rename {
+            library,
+            all,
+            folders,
+            naming,
+            dry_run,
+        } => {
+            if !all && folders.is_empty() {
+                eprintln!("Pass --all or the song folders to rename.");
+                return ExitCode::FAILURE;
+            }
+            let naming = naming.naming();
+            let (songs, _) = library.scan();
+            let song_paths: Vec<Option<PathBuf>> = songs
+                .iter()
+                .map(|song| song.song_folder_path.canonicalize().ok())
+                .collect();
+            let mut missing = false;
+            let mut selected = vec![all; songs.len()];
+            for folder in &folders {
+                let position = folder.canonicalize().ok().and_then(|folder| {
+                    song_paths
+                        .iter()
+                        .position(|path| path.as_ref() == Some(&folder))
+                });
+                match position {
+                    Some(position) => selected[position] = true,
+                    None => {
+                        missing = true;
+                        eprintln!("Not a song of the song folder: {}", folder.display());
+                    }
+                }
+            }
+            if missing {
+                return ExitCode::FAILURE;
+            }
+            let pending_changes: HashMap<Song, Action> = songs
+                .into_iter()
+                .zip(selected)
+                .filter(|(_, selected)| *selected)
+                .map(|(song, _)| (song, Action::Rename))
+                .collect();
+            let plan = ChangePlan::new(&pending_changes, &naming);
+            for change in &plan.changes {
+                if let Some(conflict) = change.conflict {
+                    if change.resolution == Resolution::Skip {
+                        println!(
+                            "Skip {} ({})",
+                            change.song.song_folder_path.display(),
+                            conflict.as_str()
+                        );
+                    }
+                }
+            }
+            if dry_run {
+                for change in plan.applied() {
+                    if let Some(destination) = change.final_destination() {
+                        println!(
+                            "Rename {} -> {}",
+                            change.song.song_folder_path.display(),
+                            destination.display()
+                        );
+                    }
+                }
+                return ExitCode::SUCCESS;
+            }
+            if apply_plan(&library.song_folder, &plan) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Command::Dedupe {
+            library,
+            keep,
+            naming,
+            dry_run,
+        } => {
+            let naming = naming.naming();
+            let (songs, _) = library.scan();
+            let duplicates = find_duplicates(&songs);
+            let rule = match keep {
+                Keep::Newest => KeepRule::Newest,
+                Keep::CanonicalName => KeepRule::CanonicalName,
+            };
+            // Sorted so that the output of repeated runs can be diffed.
+            let mut deleted = songs_to_delete(&duplicates, rule, &naming);
+            deleted.sort_by(|a, b| a.song_folder_path.cmp(&b.song_folder_path));
+            if dry_run {
+                for song in deleted {
+                    println!("Delete {}", song.song_folder_path.display());
+                }
+                return ExitCode::SUCCESS;
+            }
+            let pending_changes: HashMap<Song, Action> = deleted
+                .into_iter()
+                .map(|song| (song.clone(), Action::Delete))
+                .collect();
+            let plan = ChangePlan::new(&pending_changes, &naming);
+            if apply_plan(&library.song_folder, &plan) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Command::ExportPlaylist {
+            library,
+            output,
+            title,
+            author,
+            description,
+        } => {
+            let (songs, _) = library.scan();
+            let playlist = Playlist::new(&title, &author, &description, &songs, None);
+            match playlist.save(&output) {
+                Ok(()) => {
+                    println!("Exported {} songs to {}", songs.len(), output.display());
+                    ExitCode::SUCCESS
+                }
+                Err(error) => {
+                    eprintln!("Export playlist failed. {}", error);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Validate(library) => {
+            let (songs, invalid) = library.scan();
+            for (path, reason) in &invalid {
+                println!("Invalid\t{}\t{}", path.display(), reason);
+            }
+            for song in &songs {
+                for problem in &song.problems {
+                    println!("Warning\t{}\t{}", song.song_folder_path.display(), problem);
+                }
+            }
+            if invalid.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Command::Snapshot { library, output } => {
+            let (songs, _) = library.scan();
+            let snapshot = Snapshot::new(&library.song_folder, &songs);
+            let Some(output) = output else {
+                match serde_json::to_string_pretty(&snapshot) {
+                    Ok(text) => println!("{}", text),
+                    Err(error) => {
+                        eprintln!("Serialize snapshot failed. {}", error);
+                        return ExitCode::FAILURE;
+                    }
+                }
+                return ExitCode::SUCCESS;
+            };
+            match snapshot.save(&output) {
+                Ok(()) => {
+                    println!("Saved {} songs to {}", songs.len(), output.display());
+                    ExitCode::SUCCESS
+                }
+                Err(error) => {
+                    eprintln!("Save snapshot failed. {}", error);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Diff { old, new } => {
+            let (old, new) = match (Snapshot::load(&old), Snapshot::load(&new)) {
+                (Ok(old), Ok(new)) => (old, new),
+                (Err(error), _) | (_, Err(error)) => {
+                    eprintln!("Load snapshot failed. {}", error);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let diff = old.diff(&new);
+            for song in &diff.added {
+                println!("Added\t{}", song.song_folder_path.display());
+            }
+            for song in &diff.removed {
+                println!("Removed\t{}", song.song_folder_path.display());
+            }
+            for (old, new) in &diff.moved {
+                println!(
+                    "Moved\t{}\t{}",
+                    old.song_folder_path.display(),
+                    new.song_folder_path.display()
+                );
+            }
+            for (old, new) in &diff.changed {
+                println!(
+                    "Changed\t{}\t{}",
+                    new.song_folder_path.display(),
+                    SnapshotDiff::changed_fields(old, new).join(", ")
+                );
+            }
+            ExitCode::SUCCESS
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{naming::NamingTemplate, Song, DEFAULT_ID};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DuplicateKind {
+    /// Folders with the same level hash hold exact copies of a map.
+    SameHash,
+    /// Folders with the same BeatSaver id but different hashes hold different versions of a map.
+    SameLevelId,
+}
+
+/// Songs that hold the same map, copied out of the song list so that the groups can be kept
+/// while the list changes.
+pub(crate) struct DuplicateGroup {
+    pub(crate) kind: DuplicateKind,
+    /// The shared level hash or level id.
+    pub(crate) key: String,
+    pub(crate) songs: Vec<Song>,
+}
+
+/// Which song of a duplicate group to keep when the others get deleted.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeepRule {
+    Newest,
+    /// Keep the song already in a canonically named folder, or the newest one if there is none.
+    CanonicalName,
+}
+
+impl DuplicateGroup {
+    pub(crate) fn keeper(&self, rule: KeepRule, naming: &NamingTemplate) -> &Song {
+        let newest = self
+            .songs
+            .iter()
+            .max_by_key(|song| song.date_modified)
+            .unwrap();
+        match rule {
+            KeepRule::Newest => newest,
+            KeepRule::CanonicalName => self
+                .songs
+                .iter()
+                .find(|song| song.has_canonical_name(naming))
+                .unwrap_or(newest),
+        }
+    }
+}
+
+/// Songs to delete so that only the keeper of every group is left.
+///
+/// A song can be in a hash group and a level id group at the same time, the keeper of either
+/// group is never deleted so that no map disappears completely.
+pub(crate) fn songs_to_delete<'a>(
+    duplicates: &'a [DuplicateGroup],
+    rule: KeepRule,
+    naming: &NamingTemplate,
+) -> Vec<&'a Song> {
+    let keepers: HashSet<&Song> = duplicates
+        .iter()
+        .map(|group| group.keeper(rule, naming))
+        .collect();
+    let mut songs: Vec<&Song> = Vec::new();
+    for group in duplicates {
+        for song in &group.songs {
+            if !keepers.contains(song) && !songs.contains(&song) {
+                songs.push(song);
+            }
+        }
+    }
+    songs
+}
+
+fn group_by<'a>(
+    song_list: &'a [Song],
+    key: impl Fn(&'a Song) -> &'a str,
+) -> HashMap<&'a str, Vec<&'a Song>> {
+    let mut groups: HashMap<&str, Vec<&Song>> = HashMap::new();
+    for song in song_list {
+        groups.entry(key(song)).or_default().push(song);
+    }
+    groups
+}
+
+pub(crate) fn find_duplicates(song_list: &[Song]) -> Vec<DuplicateGroup> {
+    let mut duplicates = Vec::new();
+    for (key, songs) in group_by(song_list, |song| song.level_hash.as_str()) {
+        if songs.len() > 1 {
+            duplicates.push(DuplicateGroup {
+                kind: DuplicateKind::SameHash,
+                key: key.to_string(),
+                songs: songs.into_iter().cloned().collect(),
+            });
+        }
+    }
+    for (key, songs) in group_by(song_list, |song| song.level_id.as_str()) {
+        if key == DEFAULT_ID {
+            continue;
+        }
+        let hashes: HashSet<&str> = songs.iter().map(|song| song.level_hash.as_str()).collect();
+        if hashes.len() > 1 {
+            duplicates.push(DuplicateGroup {
+                kind: DuplicateKind::SameLevelId,
+                key: key.to_string(),
+                songs: songs.into_iter().cloned().collect(),
+            });
+        }
+    }
+    for group in &mut duplicates {
+        group
+            .songs
+            .sort_by(|a, b| a.song_folder_path.cmp(&b.song_folder_path));
+    }
+    duplicates.sort_by(|a, b| a.songs[0].song_name.cmp(&b.songs[0].song_name));
+    duplicates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use std::{
+        path::{Path, PathBuf},
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    fn song(folder: &str, level_hash: &str, level_id: &str, modified: u64) -> Song {
+        let mut song = test_util::song(Path::new("songs").join(folder), level_hash, level_id);
+        song.date_modified = Some(UNIX_EPOCH + Duration::from_secs(modified));
+        song
+    }
+
+    fn folders(songs: &[&Song]) -> Vec<PathBuf> {
+        let mut folders: Vec<PathBuf> = songs
+            .iter()
+            .map(|song| song.song_folder_path.clone())
+            .collect();
+        folders.sort();
+        folders
+    }
+
+    #[test]
+    fn groups_same_hash_and_same_level_id() {
+        let songs = [
+            song("a", "h1", "1a", 1),
+            song("b", "h1", "1a", 3),
+            song("c", "h2", "1a", 2),
+            song("d", "h3", DEFAULT_ID, 1),
+            song("e", "h3", DEFAULT_ID, 5),
+            song("f", "h4", "2b", 1),
+            song("g", "h5", DEFAULT_ID, 1),
+        ];
+        let duplicates = find_duplicates(&songs);
+        let mut groups: Vec<(bool, &str, Vec<&str>)> = duplicates
+            .iter()
+            .map(|group| {
+                (
+                    group.kind == DuplicateKind::SameHash,
+                    group.key.as_str(),
+                    group
+                        .songs
+                        .iter()
+                        .map(|song| song.song_folder_path.to_str().unwrap())
+                        .collect(),
+                )
+            })
+            .collect();
+        groups.sort();
+        assert_eq!(
+            groups,
+            [
+                (false, "1a", vec!["songs/a", "songs/b", "songs/c"]),
+                (true, "h1", vec!["songs/a", "songs/b"]),
+                (true, "h3", vec!["songs/d", "songs/e"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn copies_with_one_level_id_are_only_a_hash_group() {
+        let songs = [song("a", "h1", "1a", 1), song("b", "h1", "1a", 2)];
+        let duplicates = find_duplicates(&songs);
+        assert_eq!(duplicates.len(), 1);
+        assert!(duplicates[0].kind == DuplicateKind::SameHash);
+    }
+
+    #[test]
+    fn deletes_everything_but_the_newest() {
+        let songs = [
+            song("a", "h1", "1a", 1),
+            song("b", "h1", "1a", 3),
+            song("c", "h2", "1a", 2),
+            song("d", "h3", DEFAULT_ID, 1),
+            song("e", "h3", DEFAULT_ID, 5),
+        ];
+        let duplicates = find_duplicates(&songs);
+        let delete = songs_to_delete(&duplicates, KeepRule::Newest, &NamingTemplate::default());
+        assert_eq!(
+            folders(&delete),
+            [
+                Path::new("songs/a"),
+                Path::new("songs/c"),
+                Path::new("songs/d")
+            ]
+        );
+    }
+
+    #[test]
+    fn keeper_of_an_overlapping_group_survives() {
+        // "b" is the newest copy of h1, "c" the newest version of 1a, both have to stay.
+        let songs = [
+            song("a", "h1", "1a", 1),
+            song("b", "h1", "1a", 3),
+            song("c", "h2", "1a", 4),
+        ];
+        let duplicates = find_duplicates(&songs);
+        let delete = songs_to_delete(&duplicates, KeepRule::Newest, &NamingTemplate::default());
+        assert_eq!(folders(&delete), [Path::new("songs/a")]);
+    }
+
+    #[test]
+    fn keeps_canonically_named_song() {
+        let songs = [
+            song("1a (Song - Mapper)", "h1", "1a", 1),
+            song("copy", "h1", "1a", 2),
+        ];
+        let naming = NamingTemplate::default();
+        let duplicates = find_duplicates(&songs);
+        let delete = songs_to_delete(&duplicates, KeepRule::CanonicalName, &naming);
+        assert_eq!(folders(&delete), [Path::new("songs/copy")]);
+        let delete = songs_to_delete(&duplicates, KeepRule::Newest, &naming);
+        assert_eq!(folders(&delete), [Path::new("songs/1a (Song - Mapper)")]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{io, path::PathBuf};
+
+use crate::InvalidReason;
+
+/// Errors returned by the public API.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{}: invalid JSON at line {line} column {column}: {message}", path.display())]
+    Json {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("{}: missing field {field}", path.display())]
+    MissingField { path: PathBuf, field: String },
+    /// A file that takes part in the level hash is missing or couldn't be read.
+    #[error("{}: can't read file for the level hash: {source}", path.display())]
+    Hash {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("BeatSaver request failed: {0}")]
+    Network(String),
+    #[error("{}: not a song folder: {reason}", path.display())]
+    InvalidSong {
+        path: PathBuf,
+        reason: InvalidReason,
+    },
+    /// A change was skipped because applying it would clash with another folder.
+    #[error("{}: {reason}", path.display())]
+    Conflict { path: PathBuf, reason: &'static str },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// Tell missing fields apart from other JSON errors, serde only reports them in the message.
+    fn json(path: PathBuf, line: usize, column: usize, message: String) -> Self {
+        let field = message
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split('`').next());
+        match field {
+            Some(field) => Error::MissingField {
+                path,
+                field: field.to_string(),
+            },
+            None => Error::Json {
+                path,
+                line,
+                column,
+                message,
+            },
+        }
+    }
+
+    pub(crate) fn from_json(path: impl Into<PathBuf>, error: &serde_json::Error) -> Self {
+        Error::json(path.into(), error.line(), error.column(), error.to_string())
+    }
+
+    /// Error for the song folder `path` that failed to load for `reason`.
+    pub(crate) fn from_reason(path: PathBuf, reason: InvalidReason) -> Self {
+        match reason {
+            InvalidReason::ReadFailed {
+                file,
+                kind,
+                message,
+            } => {
+                let path = if file.is_empty() {
+                    path
+                } else {
+                    path.join(file)
+                };
+                Error::io(path, io::Error::new(kind, message))
+            }
+            InvalidReason::InfoDatParse {
+                file,
+                line,
+                column,
+                message,
+            } => Error::json(path.join(file), line, column, message),
+            InvalidReason::MissingFile(name) => Error::Hash {
+                path: path.join(name),
+                source: io::Error::new(io::ErrorKind::NotFound, "file not found"),
+            },
+            InvalidReason::HashReadFailed {
+                file,
+                kind,
+                message,
+            } => Error::Hash {
+                path: path.join(file),
+                source: io::Error::new(kind, message),
+            },
+            InvalidReason::MissingAudio(name) => Error::io(
+                path.join(name),
+                io::Error::new(io::ErrorKind::NotFound, "audio file not found"),
+            ),
+            reason => Error::InvalidSong { path, reason },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_failure_keeps_kind_and_file() {
+        let reason = InvalidReason::read_failed(
+            "Info.dat",
+            &io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+        );
+        let reason: InvalidReason =
+            serde_json::from_str(&serde_json::to_string(&reason).unwrap()).unwrap();
+        match Error::from_reason(PathBuf::from("song"), reason) {
+            Error::Io { path, source } => {
+                assert_eq!(path, PathBuf::from("song").join("Info.dat"));
+                assert_eq!(source.kind(), io::ErrorKind::PermissionDenied);
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn parse_error_uses_info_dat_name() {
+        let error = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let reason = InvalidReason::from_json("info.dat", &error);
+        match Error::from_reason(PathBuf::from("song"), reason) {
+            Error::Json { path, .. } => assert_eq!(path, PathBuf::from("song").join("info.dat")),
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn missing_audio_is_not_found() {
+        let reason = InvalidReason::MissingAudio("song.egg".to_string());
+        match Error::from_reason(PathBuf::from("song"), reason) {
+            Error::Io { path, source } => {
+                assert_eq!(path, PathBuf::from("song").join("song.egg"));
+                assert_eq!(source.kind(), io::ErrorKind::NotFound);
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn unreadable_hash_files_are_hash_errors() {
+        for (reason, kind) in [
+            (
+                InvalidReason::MissingFile("Expert.dat".to_string()),
+                io::ErrorKind::NotFound,
+            ),
+            (
+                InvalidReason::HashReadFailed {
+                    file: "Expert.dat".to_string(),
+                    kind: io::ErrorKind::PermissionDenied,
+                    message: "denied".to_string(),
+                },
+                io::ErrorKind::PermissionDenied,
+            ),
+        ] {
+            match Error::from_reason(PathBuf::from("song"), reason) {
+                Error::Hash { path, source } => {
+                    assert_eq!(path, PathBuf::from("song").join("Expert.dat"));
+                    assert_eq!(source.kind(), kind);
+                }
+                error => panic!("unexpected error {:?}", error),
+            }
+        }
+    }
+}
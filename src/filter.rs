@@ -0,0 +1,283 @@
+use deunicode::deunicode;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{BeatmapCharacteristic, Song, DEFAULT_ID};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LevelIdFilter {
+    Any,
+    Known,
+    Unknown,
+}
+
+/// Conditions to narrow down the song list, a default filter matches every song.
+#[derive(Clone, PartialEq)]
+pub(crate) struct SongFilter {
+    /// Words that all have to appear in the song name, sub name, song author or level author.
+    pub(crate) text: String,
+    pub(crate) bpm_enabled: bool,
+    pub(crate) min_bpm: f64,
+    pub(crate) max_bpm: f64,
+    pub(crate) characteristic: Option<BeatmapCharacteristic>,
+    pub(crate) difficulty: Option<String>,
+    pub(crate) level_id: LevelIdFilter,
+}
+
+impl Default for SongFilter {
+    fn default() -> Self {
+        SongFilter {
+            text: String::new(),
+            bpm_enabled: false,
+            min_bpm: 0.0,
+            max_bpm: 300.0,
+            characteristic: None,
+            difficulty: None,
+            level_id: LevelIdFilter::Any,
+        }
+    }
+}
+
+/// Lowercase romanized form of `text`, so that e.g. `kimi` finds `君`.
+fn normalize(text: &str) -> String {
+    deunicode(text).to_lowercase()
+}
+
+/// The fields of a song that text search looks at, both lowercase and normalized. Built once per
+/// song, see [`SearchTexts`].
+#[derive(Clone, Debug)]
+pub(crate) struct SearchText {
+    raw: String,
+    normalized: String,
+}
+
+impl SearchText {
+    pub(crate) fn new(song: &Song) -> Self {
+        // Words never contain a line break, so they can't match across two fields.
+        let fields = [
+            song.song_name.as_str(),
+            &song.song_sub_name,
+            &song.song_author_name,
+            &song.level_author_name,
+        ]
+        .join("\n");
+        SearchText {
+            raw: fields.to_lowercase(),
+            normalized: normalize(&fields),
+        }
+    }
+}
+
+/// Search text of the listed songs by folder, updated when the song list changes.
+#[derive(Default)]
+pub(crate) struct SearchTexts(HashMap<PathBuf, SearchText>);
+
+impl SearchTexts {
+    /// Build the search text of the songs that don't have one yet and drop those of songs that
+    /// are no longer listed.
+    pub(crate) fn update(&mut self, songs: &[Song]) {
+        let paths: HashSet<&Path> = songs
+            .iter()
+            .map(|song| song.song_folder_path.as_path())
+            .collect();
+        self.0.retain(|path, _| paths.contains(path.as_path()));
+        for song in songs {
+            if !self.0.contains_key(&song.song_folder_path) {
+                self.0
+                    .insert(song.song_folder_path.clone(), SearchText::new(song));
+            }
+        }
+    }
+
+    /// Forget the search text of the song in `path`, as it changed.
+    pub(crate) fn remove(&mut self, path: &Path) {
+        self.0.remove(path);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// A [`SongFilter`] with its search words prepared, to test many songs against it.
+pub(crate) struct SongMatcher<'a> {
+    filter: &'a SongFilter,
+    search_texts: &'a SearchTexts,
+    /// Each word lowercase and normalized.
+    words: Vec<(String, String)>,
+}
+
+impl SongFilter {
+    pub(crate) fn is_empty(&self) -> bool {
+        self == &SongFilter::default()
+    }
+
+    /// Prepare to test songs, looking up their search text in `search_texts`.
+    pub(crate) fn matcher<'a>(&'a self, search_texts: &'a SearchTexts) -> SongMatcher<'a> {
+        SongMatcher {
+            filter: self,
+            search_texts,
+            words: self
+                .text
+                .split_whitespace()
+                .map(|word| (word.to_lowercase(), normalize(word)))
+                .collect(),
+        }
+    }
+}
+
+impl SongMatcher<'_> {
+    pub(crate) fn matches(&self, song: &Song) -> bool {
+        let filter = self.filter;
+        if filter.bpm_enabled
+            && (song.beats_per_minute < filter.min_bpm || song.beats_per_minute > filter.max_bpm)
+        {
+            return false;
+        }
+        if let Some(characteristic) = &filter.characteristic {
+            if !song.has_characteristic(characteristic) {
+                return false;
+            }
+        }
+        if let Some(difficulty) = &filter.difficulty {
+            if !song.has_difficulty(difficulty) {
+                return false;
+            }
+        }
+        match filter.level_id {
+            LevelIdFilter::Any => {}
+            LevelIdFilter::Known if song.level_id == DEFAULT_ID => return false,
+            LevelIdFilter::Unknown if song.level_id != DEFAULT_ID => return false,
+            _ => {}
+        }
+        if self.words.is_empty() {
+            return true;
+        }
+        let built;
+        let search_text = match self.search_texts.0.get(&song.song_folder_path) {
+            Some(search_text) => search_text,
+            None => {
+                built = SearchText::new(song);
+                &built
+            }
+        };
+        self.words.iter().all(|(raw_word, normalized_word)| {
+            search_text.raw.contains(raw_word.as_str())
+                || search_text.normalized.contains(normalized_word.as_str())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn song(song_name: &str, level_author_name: &str) -> Song {
+        let mut song = Song::placeholder(Path::new(song_name));
+        song.song_name = song_name.to_string();
+        song.level_author_name = level_author_name.to_string();
+        song
+    }
+
+    /// Test `song` the way the song list does, with its search text built beforehand.
+    fn matches(filter: &SongFilter, song: &Song) -> bool {
+        let mut search_texts = SearchTexts::default();
+        search_texts.update(std::slice::from_ref(song));
+        filter.matcher(&search_texts).matches(song)
+    }
+
+    fn text_filter(text: &str) -> SongFilter {
+        SongFilter {
+            text: text.to_string(),
+            ..SongFilter::default()
+        }
+    }
+
+    #[test]
+    fn default_filter_matches_everything() {
+        assert!(SongFilter::default().is_empty());
+        assert!(matches(&SongFilter::default(), &song("", "")));
+    }
+
+    #[test]
+    fn text_matches_romanized_and_case_insensitive() {
+        let song = song("君の知らない物語", "Mapper");
+        assert!(matches(&text_filter("君の"), &song));
+        assert!(matches(&text_filter("jun"), &song));
+        assert!(matches(&text_filter("MAPPER"), &song));
+        let cafe = self::song("Café", "");
+        assert!(matches(&text_filter("cafe"), &cafe));
+        assert!(matches(&text_filter("café"), &cafe));
+    }
+
+    #[test]
+    fn every_word_has_to_match_within_a_field() {
+        let song = song("Night Sky", "Mapper");
+        assert!(matches(&text_filter("sky mapper"), &song));
+        assert!(!matches(&text_filter("sky other"), &song));
+        assert!(!matches(&text_filter("skymapper"), &song));
+    }
+
+    #[test]
+    fn bpm_range_is_inclusive() {
+        let filter = SongFilter {
+            bpm_enabled: true,
+            min_bpm: 100.0,
+            max_bpm: 200.0,
+            ..SongFilter::default()
+        };
+        for (bpm, expected) in [(99.9, false), (100.0, true), (200.0, true), (200.1, false)] {
+            let mut song = song("song", "");
+            song.beats_per_minute = bpm;
+            assert_eq!(matches(&filter, &song), expected, "bpm {}", bpm);
+        }
+        let mut fast = song("song", "");
+        fast.beats_per_minute = 400.0;
+        assert!(matches(&SongFilter::default(), &fast));
+    }
+
+    #[test]
+    fn level_id_filter() {
+        let unknown = song("unknown", "");
+        let mut known = song("known", "");
+        known.level_id = "1a2b".to_string();
+        let level_id_matches = |level_id, song: &Song| {
+            let filter = SongFilter {
+                level_id,
+                ..SongFilter::default()
+            };
+            matches(&filter, song)
+        };
+        assert!(
+            level_id_matches(LevelIdFilter::Any, &known)
+                && level_id_matches(LevelIdFilter::Any, &unknown)
+        );
+        assert!(
+            level_id_matches(LevelIdFilter::Known, &known)
+                && !level_id_matches(LevelIdFilter::Known, &unknown)
+        );
+        assert!(
+            !level_id_matches(LevelIdFilter::Unknown, &known)
+                && level_id_matches(LevelIdFilter::Unknown, &unknown)
+        );
+    }
+
+    #[test]
+    fn search_texts_follow_the_song_list() {
+        let old = song("Old Name", "");
+        let mut search_texts = SearchTexts::default();
+        search_texts.update(std::slice::from_ref(&old));
+        let mut renamed = old.clone();
+        renamed.song_name = "New Name".to_string();
+        let filter = text_filter("new");
+        assert!(!filter.matcher(&search_texts).matches(&renamed));
+        search_texts.remove(&renamed.song_folder_path);
+        search_texts.update(std::slice::from_ref(&renamed));
+        assert!(filter.matcher(&search_texts).matches(&renamed));
+        search_texts.update(&[]);
+        assert!(search_texts.0.is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{read_dir, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{InvalidReason, Song, DATA_DIR_NAME};
+
+/// Bumped whenever the fields of [`Song`] change, older indexes are thrown away.
+const INDEX_VERSION: u32 = 2;
+
+/// Size and modification time of one file of a song folder.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct FileStamp {
+    /// Path relative to the song folder.
+    path: String,
+    size: u64,
+    /// Nanoseconds since the Unix epoch.
+    modified: u64,
+}
+
+/// What a song folder was loaded as.
+#[derive(Serialize, Deserialize)]
+enum IndexedFolder {
+    Song(Box<Song>),
+    Invalid(InvalidReason),
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    path: PathBuf,
+    files: Vec<FileStamp>,
+    folder: IndexedFolder,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexFile {
+    version: u32,
+    entries: Vec<IndexEntry>,
+}
+
+/// Songs and invalid folders found by the last scan, so that folders whose files haven't
+/// changed since don't have to be parsed and hashed again.
+#[derive(Default)]
+pub(crate) struct LibraryIndex {
+    entries: HashMap<PathBuf, IndexEntry>,
+}
+
+impl LibraryIndex {
+    fn path(song_folder: &Path) -> PathBuf {
+        song_folder.join(DATA_DIR_NAME).join("index.json")
+    }
+
+    /// Read the index of `song_folder`, an index that is missing, broken or from another
+    /// version is treated as empty.
+    pub(crate) fn load(song_folder: &Path) -> Self {
+        let path = LibraryIndex::path(song_folder);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(error) => {
+                info!("Load library index failed. {}", error);
+                return LibraryIndex::default();
+            }
+        };
+        let index: IndexFile = match serde_json::from_reader(BufReader::new(file)) {
+            Ok(index) => index,
+            Err(error) => {
+                warn!("Parse library index failed. {}", error);
+                return LibraryIndex::default();
+            }
+        };
+        if index.version != INDEX_VERSION {
+            info!(
+                "Library index version {} is outdated, rescanning.",
+                index.version
+            );
+            return LibraryIndex::default();
+        }
+        LibraryIndex {
+            entries: index
+                .entries
+                .into_iter()
+                .map(|entry| (entry.path.clone(), entry))
+                .collect(),
+        }
+    }
+
+    /// Write the index into the data folder of `song_folder`. It is written to a temporary
+    /// file first, so that a failed save leaves the previous index intact.
+    pub(crate) fn save(self, song_folder: &Path) -> io::Result<()> {
+        let path = LibraryIndex::path(song_folder);
+        std::fs::create_dir_all(song_folder.join(DATA_DIR_NAME))?;
+        let index = IndexFile {
+            version: INDEX_VERSION,
+            entries: self.entries.into_values().collect(),
+        };
+        let temp_path = path.with_extension("json.tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(&mut writer, &index)?;
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(&temp_path, &path)
+    }
+
+    /// What `song_path` was loaded as, if the folder still holds exactly the same `files`.
+    pub(crate) fn get(
+        &self,
+        song_path: &Path,
+        files: &[FileStamp],
+    ) -> Option<Result<&Song, &InvalidReason>> {
+        let entry = self
+            .entries
+            .get(song_path)
+            .filter(|entry| entry.files == files)?;
+        Some(match &entry.folder {
+            IndexedFolder::Song(song) => Ok(song),
+            IndexedFolder::Invalid(reason) => Err(reason),
+        })
+    }
+
+    /// Remember what `song_path` was loaded as. Read failures aren't kept, they may not happen
+    /// again even if the files stay the same.
+    pub(crate) fn insert(
+        &mut self,
+        song_path: &Path,
+        files: Vec<FileStamp>,
+        song: Result<&Song, &InvalidReason>,
+    ) {
+        let folder = match song {
+            Ok(song) => IndexedFolder::Song(Box::new(song.clone())),
+            Err(InvalidReason::ReadFailed { .. } | InvalidReason::HashReadFailed { .. }) => {
+                self.entries.remove(song_path);
+                return;
+            }
+            Err(reason) => IndexedFolder::Invalid(reason.clone()),
+        };
+        self.entries.insert(
+            song_path.to_path_buf(),
+            IndexEntry {
+                path: song_path.to_path_buf(),
+                files,
+                folder,
+            },
+        );
+    }
+
+    pub(crate) fn remove(&mut self, song_path: &Path) {
+        self.entries.remove(song_path);
+    }
+
+    /// Move the entry of `song_path` over from `other`, used for folders a cancelled scan
+    /// didn't get to.
+    pub(crate) fn keep(&mut self, other: &mut LibraryIndex, song_path: &Path) {
+        if let Some(entry) = other.entries.remove(song_path) {
+            self.entries.insert(song_path.to_path_buf(), entry);
+        }
+    }
+}
+
+/// Stamps of every file under `song_path`, sorted by path.
+pub(crate) fn file_stamps(song_path: &Path) -> Vec<FileStamp> {
+    let mut stamps = Vec::new();
+    collect_stamps(song_path, song_path, &mut stamps);
+    stamps.sort_by(|a, b| a.path.cmp(&b.path));
+    stamps
+}
+
+fn collect_stamps(song_path: &Path, path: &Path, stamps: &mut Vec<FileStamp>) {
+    let entries = match read_dir(path) {
+        Ok(entries) => entries,
+        Err(error) => {
+            warn!("Read file list of {} failed. {}", path.display(), error);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let entry_path = entry.path();
+        if metadata.is_dir() {
+            collect_stamps(song_path, &entry_path, stamps);
+            continue;
+        }
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+        stamps.push(FileStamp {
+            path: entry_path
+                .strip_prefix(song_path)
+                .unwrap_or(&entry_path)
+                .to_string_lossy()
+                .to_string(),
+            size: metadata.len(),
+            modified,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFolder;
+
+    fn stamp(path: &str, size: u64, modified: u64) -> FileStamp {
+        FileStamp {
+            path: path.to_string(),
+            size,
+            modified,
+        }
+    }
+
+    #[test]
+    fn entry_only_matches_same_files() {
+        let song_path = Path::new("song");
+        let files = vec![stamp("Info.dat", 100, 1), stamp("song.egg", 200, 1)];
+        let mut index = LibraryIndex::default();
+        index.insert(song_path, files.clone(), Ok(&Song::placeholder(song_path)));
+        assert!(matches!(index.get(song_path, &files), Some(Ok(_))));
+        let touched = vec![stamp("Info.dat", 100, 2), stamp("song.egg", 200, 1)];
+        assert!(index.get(song_path, &touched).is_none());
+        let resized = vec![stamp("Info.dat", 101, 1), stamp("song.egg", 200, 1)];
+        assert!(index.get(song_path, &resized).is_none());
+        assert!(index.get(song_path, &files[..1]).is_none());
+        assert!(index.get(Path::new("other"), &files).is_none());
+    }
+
+    #[test]
+    fn keeps_invalid_folders_but_not_read_failures() {
+        let files = vec![stamp("readme.txt", 10, 1)];
+        let mut index = LibraryIndex::default();
+        index.insert(
+            Path::new("a"),
+            files.clone(),
+            Err(&InvalidReason::NoInfoDat),
+        );
+        let read_failed = InvalidReason::read_failed("", &io::ErrorKind::PermissionDenied.into());
+        index.insert(Path::new("b"), files.clone(), Err(&read_failed));
+        assert!(matches!(
+            index.get(Path::new("a"), &files),
+            Some(Err(InvalidReason::NoInfoDat))
+        ));
+        assert!(index.get(Path::new("b"), &files).is_none());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let song_folder = TempFolder::new("save");
+        let song_path = song_folder.join("song");
+        let files = vec![stamp("Info.dat", 100, 1)];
+        let mut index = LibraryIndex::default();
+        index.insert(
+            &song_path,
+            files.clone(),
+            Ok(&Song::placeholder(&song_path)),
+        );
+        index.save(&song_folder).unwrap();
+        assert!(!LibraryIndex::path(&song_folder)
+            .with_extension("json.tmp")
+            .exists());
+        let loaded = LibraryIndex::load(&song_folder);
+        assert!(matches!(loaded.get(&song_path, &files), Some(Ok(_))));
+    }
+
+    #[test]
+    fn other_version_is_discarded() {
+        let song_folder = TempFolder::new("version");
+        let song_path = song_folder.join("song");
+        let files = vec![stamp("Info.dat", 100, 1)];
+        let mut index = LibraryIndex::default();
+        index.insert(
+            &song_path,
+            files.clone(),
+            Ok(&Song::placeholder(&song_path)),
+        );
+        index.save(&song_folder).unwrap();
+        let path = LibraryIndex::path(&song_folder);
+        let mut json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        json["version"] = (INDEX_VERSION + 1).into();
+        std::fs::write(&path, json.to_string()).unwrap();
+        assert!(LibraryIndex::load(&song_folder).entries.is_empty());
+    }
+
+    #[test]
+    fn keep_moves_entries_of_skipped_folders() {
+        let files = vec![stamp("Info.dat", 100, 1)];
+        let mut old = LibraryIndex::default();
+        for name in ["scanned", "skipped"] {
+            let song_path = Path::new(name);
+            old.insert(song_path, files.clone(), Ok(&Song::placeholder(song_path)));
+        }
+        let mut updated = LibraryIndex::default();
+        updated.keep(&mut old, Path::new("skipped"));
+        updated.keep(&mut old, Path::new("missing"));
+        assert!(updated.get(Path::new("skipped"), &files).is_some());
+        assert!(updated.get(Path::new("scanned"), &files).is_none());
+        assert!(old.get(Path::new("skipped"), &files).is_none());
+    }
+}
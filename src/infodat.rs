@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+
+/// Just enough of an `info.dat` to tell which format the rest of it is in.
+#[derive(Deserialize)]
+pub(crate) struct InfoDatVersion {
+    #[serde(rename = "version", default)]
+    version: Option<String>,
+    #[serde(rename = "_version", default)]
+    legacy_version: Option<String>,
+}
+
+impl InfoDatVersion {
+    pub(crate) fn as_str(&self) -> &str {
+        self.version
+            .as_deref()
+            .or(self.legacy_version.as_deref())
+            .unwrap_or("2.0.0")
+    }
+    /// Major version of the format, maps without any version field are treated as v2.
+    pub(crate) fn major(&self) -> u64 {
+        self.as_str()
+            .split('.')
+            .next()
+            .and_then(|major| major.parse().ok())
+            .unwrap_or(2)
+    }
+}
+
+/// Typed model of a v2 `info.dat`.
+///
+/// Refer https://bsmg.wiki/mapping/map-format/info.html
+#[derive(Deserialize)]
+pub(crate) struct InfoDat {
+    #[serde(rename = "_songName")]
+    pub(crate) song_name: String,
+    #[serde(rename = "_songSubName", default)]
+    pub(crate) song_sub_name: String,
+    #[serde(rename = "_songAuthorName", default)]
+    pub(crate) song_author_name: String,
+    #[serde(rename = "_levelAuthorName", default)]
+    pub(crate) level_author_name: String,
+    #[serde(rename = "_beatsPerMinute")]
+    pub(crate) beats_per_minute: f64,
+    #[serde(rename = "_songTimeOffset", default)]
+    pub(crate) song_time_offset: f64,
+    #[serde(rename = "_shuffle", default)]
+    pub(crate) shuffle: f64,
+    #[serde(rename = "_shufflePeriod", default)]
+    pub(crate) shuffle_period: f64,
+    #[serde(rename = "_previewStartTime", default)]
+    pub(crate) preview_start_time: f64,
+    #[serde(rename = "_previewDuration", default)]
+    pub(crate) preview_duration: f64,
+    #[serde(rename = "_songFilename")]
+    pub(crate) song_filename: String,
+    #[serde(rename = "_coverImageFilename", default)]
+    pub(crate) cover_image_filename: String,
+    #[serde(rename = "_environmentName", default)]
+    pub(crate) environment_name: String,
+    #[serde(rename = "_allDirectionsEnvironmentName", default)]
+    pub(crate) all_directions_environment_name: String,
+    #[serde(rename = "_customData", default)]
+    pub(crate) custom_data: InfoDatCustomData,
+    #[serde(rename = "_difficultyBeatmapSets")]
+    pub(crate) difficulty_beatmap_sets: Vec<InfoDatDifficultyBeatmapSet>,
+}
+
+/// Song level `_customData` used by SongCore, v4 maps use the same keys without underscore.
+///
+/// Refer https://github.com/Kylemc1413/SongCore#infodat-explanation
+#[derive(Default, Deserialize)]
+pub(crate) struct InfoDatCustomData {
+    #[serde(rename = "_contributors", alias = "contributors", default)]
+    pub(crate) contributors: Vec<Contributor>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Contributor {
+    #[serde(rename = "_role", alias = "role", default)]
+    pub(crate) role: String,
+    #[serde(rename = "_name", alias = "name", default)]
+    pub(crate) name: String,
+    #[serde(rename = "_iconPath", alias = "iconPath", default)]
+    pub(crate) icon_path: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct InfoDatDifficultyBeatmapSet {
+    #[serde(rename = "_beatmapCharacteristicName")]
+    pub(crate) beatmap_characteristic_name: String,
+    #[serde(rename = "_difficultyBeatmaps")]
+    pub(crate) difficulty_beatmaps: Vec<InfoDatDifficultyBeatmap>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct InfoDatDifficultyBeatmap {
+    #[serde(rename = "_difficulty")]
+    pub(crate) difficulty: String,
+    #[serde(rename = "_difficultyRank")]
+    pub(crate) difficulty_rank: u64,
+    #[serde(rename = "_beatmapFilename")]
+    pub(crate) beatmap_filename: String,
+    #[serde(rename = "_noteJumpMovementSpeed", default)]
+    pub(crate) note_jump_movement_speed: f64,
+    #[serde(rename = "_noteJumpStartBeatOffset", default)]
+    pub(crate) note_jump_start_beat_offset: f64,
+    #[serde(rename = "_customData", default)]
+    pub(crate) custom_data: DifficultyCustomData,
+}
+
+/// Difficulty level `_customData` used by SongCore.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DifficultyCustomData {
+    #[serde(rename = "_difficultyLabel", alias = "difficultyLabel", default)]
+    pub(crate) difficulty_label: Option<String>,
+    #[serde(rename = "_requirements", alias = "requirements", default)]
+    pub(crate) requirements: Vec<String>,
+    #[serde(rename = "_suggestions", alias = "suggestions", default)]
+    pub(crate) suggestions: Vec<String>,
+    #[serde(rename = "_warnings", alias = "warnings", default)]
+    pub(crate) warnings: Vec<String>,
+    #[serde(rename = "_information", alias = "information", default)]
+    pub(crate) information: Vec<String>,
+}
+
+/// Typed model of a v4 `Info.dat`.
+///
+/// Refer https://bsmg.wiki/mapping/map-format/info.html
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InfoDatV4 {
+    pub(crate) song: InfoDatV4Song,
+    pub(crate) audio: InfoDatV4Audio,
+    #[serde(default)]
+    pub(crate) cover_image_filename: String,
+    #[serde(default)]
+    pub(crate) environment_names: Vec<String>,
+    #[serde(default)]
+    pub(crate) difficulty_beatmaps: Vec<InfoDatV4DifficultyBeatmap>,
+    #[serde(default)]
+    pub(crate) custom_data: InfoDatCustomData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InfoDatV4Song {
+    pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) sub_title: String,
+    #[serde(default)]
+    pub(crate) author: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InfoDatV4Audio {
+    pub(crate) song_filename: String,
+    #[serde(default)]
+    pub(crate) audio_data_filename: String,
+    pub(crate) bpm: f64,
+    #[serde(default)]
+    pub(crate) preview_start_time: f64,
+    #[serde(default)]
+    pub(crate) preview_duration: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InfoDatV4DifficultyBeatmap {
+    pub(crate) characteristic: String,
+    pub(crate) difficulty: String,
+    #[serde(default)]
+    pub(crate) beatmap_authors: InfoDatV4BeatmapAuthors,
+    #[serde(default)]
+    pub(crate) environment_name_idx: usize,
+    #[serde(default)]
+    pub(crate) note_jump_movement_speed: f64,
+    #[serde(default)]
+    pub(crate) note_jump_start_beat_offset: f64,
+    pub(crate) beatmap_data_filename: String,
+    #[serde(default)]
+    pub(crate) lightshow_data_filename: String,
+    #[serde(default)]
+    pub(crate) custom_data: DifficultyCustomData,
+}
+
+#[derive(Default, Deserialize)]
+pub(crate) struct InfoDatV4BeatmapAuthors {
+    #[serde(default)]
+    pub(crate) mappers: Vec<String>,
+    #[serde(default)]
+    pub(crate) lighters: Vec<String>,
+}
@@ -0,0 +1,167 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::DATA_DIR_NAME;
+
+/// Why an entry of the song folder couldn't be loaded as a song, or what is wrong with a song
+/// that could.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InvalidReason {
+    NotADirectory,
+    /// Reading `file`, relative to the song folder, failed. An empty `file` is the song folder
+    /// itself.
+    ReadFailed {
+        file: String,
+        #[serde(with = "io_kind")]
+        kind: io::ErrorKind,
+        message: String,
+    },
+    NoInfoDat,
+    InfoDatParse {
+        /// Name of the info.dat file, its case differs between maps.
+        file: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A difficulty, lightshow or audio data file named in info.dat doesn't exist.
+    MissingFile(String),
+    /// A file that takes part in the level hash exists but couldn't be read.
+    HashReadFailed {
+        file: String,
+        #[serde(with = "io_kind")]
+        kind: io::ErrorKind,
+        message: String,
+    },
+    MissingAudio(String),
+    MissingCover(String),
+    /// Characteristic that the base game doesn't know, it needs a mod to be played.
+    UnknownCharacteristic(String),
+}
+
+impl InvalidReason {
+    pub(crate) fn read_failed(file: impl Into<String>, error: &io::Error) -> Self {
+        InvalidReason::ReadFailed {
+            file: file.into(),
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+
+    pub(crate) fn from_json(file: impl Into<String>, error: &serde_json::Error) -> Self {
+        InvalidReason::InfoDatParse {
+            file: file.into(),
+            line: error.line(),
+            column: error.column(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for InvalidReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidReason::NotADirectory => write!(f, "not a directory"),
+            InvalidReason::ReadFailed { file, message, .. } if file.is_empty() => {
+                write!(f, "read failed: {}", message)
+            }
+            InvalidReason::ReadFailed { file, message, .. } => {
+                write!(f, "read {} failed: {}", file, message)
+            }
+            InvalidReason::NoInfoDat => write!(f, "no info.dat"),
+            InvalidReason::InfoDatParse {
+                file,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "{} parse error at line {} column {}: {}",
+                file, line, column, message
+            ),
+            InvalidReason::MissingFile(name) => write!(f, "missing file {}", name),
+            InvalidReason::HashReadFailed { file, message, .. } => {
+                write!(f, "read {} for the level hash failed: {}", file, message)
+            }
+            InvalidReason::MissingAudio(name) => write!(f, "missing audio file {}", name),
+            InvalidReason::MissingCover(name) => write!(f, "missing cover file {}", name),
+            InvalidReason::UnknownCharacteristic(name) => {
+                write!(f, "unknown characteristic {}", name)
+            }
+        }
+    }
+}
+
+/// Serialize `io::ErrorKind` by name, kinds this doesn't know come back as `Other`.
+mod io_kind {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::io::ErrorKind;
+
+    const KINDS: [ErrorKind; 8] = [
+        ErrorKind::NotFound,
+        ErrorKind::PermissionDenied,
+        ErrorKind::AlreadyExists,
+        ErrorKind::InvalidInput,
+        ErrorKind::InvalidData,
+        ErrorKind::TimedOut,
+        ErrorKind::Interrupted,
+        ErrorKind::UnexpectedEof,
+    ];
+
+    pub(super) fn serialize<S: Serializer>(
+        kind: &ErrorKind,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", kind))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ErrorKind, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(KINDS
+            .into_iter()
+            .find(|kind| format!("{:?}", kind) == name)
+            .unwrap_or(ErrorKind::Other))
+    }
+}
+
+/// Move an invalid entry into the quarantine folder, where it is no longer scanned.
+pub(crate) fn quarantine(path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let song_folder = path.parent().ok_or("path has no parent")?;
+    let name = path.file_name().ok_or("path has no name")?;
+    let quarantine_folder = song_folder.join(DATA_DIR_NAME).join("quarantine");
+    std::fs::create_dir_all(&quarantine_folder)?;
+    let mut destination = quarantine_folder.join(name);
+    let mut number = 2;
+    while destination.exists() {
+        destination = quarantine_folder.join(format!("{} ({})", name.to_string_lossy(), number));
+        number += 1;
+    }
+    info!(
+        "Moving {} to quarantine {}",
+        path.display(),
+        destination.display()
+    );
+    std::fs::rename(path, &destination)?;
+    Ok(destination)
+}
+
+/// Show `path` in the file manager of the system.
+pub(crate) fn open_in_file_manager(path: &Path) -> Result<(), Box<dyn Error>> {
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    Command::new(program).arg(path).spawn()?;
+    Ok(())
+}
@@ -0,0 +1,337 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs::File,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{trash::Trash, Action, DATA_DIR_NAME};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum Outcome {
+    Succeeded,
+    Failed(String),
+}
+
+/// How far undoing a succeeded operation got.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum UndoState {
+    #[default]
+    Pending,
+    Undone,
+    /// The operation can't be reversed any more, e.g. its folder got purged from the trash.
+    Unrecoverable(String),
+}
+
+/// A single rename or delete of a committed batch.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JournalOperation {
+    pub(crate) action: Action,
+    pub(crate) source: PathBuf,
+    /// New folder of a rename, or the folder in the trash of a delete.
+    pub(crate) destination: PathBuf,
+    /// Trash entry of a delete, used to restore it.
+    #[serde(default)]
+    pub(crate) trash_id: Option<String>,
+    /// Seconds since the unix epoch.
+    pub(crate) timestamp: u64,
+    pub(crate) outcome: Outcome,
+    #[serde(default)]
+    pub(crate) undo: UndoState,
+}
+
+/// Every operation performed by one press of Commit.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JournalBatch {
+    /// Seconds since the unix epoch.
+    pub(crate) committed_at: u64,
+    pub(crate) operations: Vec<JournalOperation>,
+    /// Seconds since the unix epoch when the last pending operation of the batch got resolved.
+    #[serde(default)]
+    pub(crate) undone_at: Option<u64>,
+}
+
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+impl JournalBatch {
+    pub(crate) fn new() -> Self {
+        JournalBatch {
+            committed_at: unix_time(),
+            operations: Vec::new(),
+            undone_at: None,
+        }
+    }
+
+    pub(crate) fn committed_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.committed_at)
+    }
+
+    pub(crate) fn succeeded(&self) -> impl Iterator<Item = &JournalOperation> {
+        self.operations
+            .iter()
+            .filter(|operation| matches!(operation.outcome, Outcome::Succeeded))
+    }
+
+    /// Succeeded operations that haven't been undone and can still be.
+    pub(crate) fn pending(&self) -> impl Iterator<Item = &JournalOperation> {
+        self.succeeded()
+            .filter(|operation| operation.undo == UndoState::Pending)
+    }
+
+    fn is_undoable(&self) -> bool {
+        self.undone_at.is_none() && self.pending().next().is_some()
+    }
+}
+
+/// Why an operation couldn't be undone.
+enum UndoError {
+    /// Might work on a later try, e.g. once the folder in the way is moved.
+    Retry(String),
+    Unrecoverable(String),
+}
+
+/// Record of the committed batches, kept in the data folder of the song folder.
+pub(crate) struct Journal {
+    path: PathBuf,
+    song_folder: PathBuf,
+}
+
+impl Journal {
+    pub(crate) fn new(song_folder: &Path) -> Self {
+        Journal {
+            path: song_folder.join(DATA_DIR_NAME).join("journal.json"),
+            song_folder: song_folder.to_path_buf(),
+        }
+    }
+
+    pub(crate) fn batches(&self) -> Vec<JournalBatch> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(_) => return Vec::new(),
+        };
+        match serde_json::from_reader(file) {
+            Ok(batches) => batches,
+            Err(error) => {
+                warn!("Parse journal failed. {}", error);
+                Vec::new()
+            }
+        }
+    }
+
+    fn save(&self, batches: &[JournalBatch]) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        serde_json::to_writer_pretty(File::create(&self.path)?, batches)?;
+        Ok(())
+    }
+
+    pub(crate) fn append(&self, batch: JournalBatch) -> Result<(), Box<dyn Error>> {
+        let mut batches = self.batches();
+        batches.push(batch);
+        self.save(&batches)
+    }
+
+    /// The most recent batch that still has operations to undo.
+    pub(crate) fn last_undoable(&self) -> Option<JournalBatch> {
+        self.batches()
+            .into_iter()
+            .rev()
+            .find(JournalBatch::is_undoable)
+    }
+
+    /// Trash entries of deletes that can still be undone.
+    pub(crate) fn referenced_trash_ids(&self) -> HashSet<String> {
+        self.batches()
+            .iter()
+            .filter(|batch| batch.is_undoable())
+            .flat_map(|batch| batch.pending())
+            .filter_map(|operation| operation.trash_id.clone())
+            .collect()
+    }
+
+    /// Reverse the pending operations of the last undoable batch: renamed folders get their old
+    /// name back and deleted folders get restored from the trash.
+    ///
+    /// Returns the errors of the operations that couldn't be reversed. Those that may work later
+    /// stay pending so the next undo retries them, those that never will are marked unrecoverable
+    /// so they don't keep older batches from being undone.
+    pub(crate) fn undo_last(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut batches = self.batches();
+        let batch = batches
+            .iter_mut()
+            .rev()
+            .find(|batch| batch.is_undoable())
+            .ok_or("nothing to undo")?;
+        let trash = Trash::new(&self.song_folder);
+        let mut errors = Vec::new();
+        for operation in batch.operations.iter_mut().rev() {
+            if !matches!(operation.outcome, Outcome::Succeeded)
+                || operation.undo != UndoState::Pending
+            {
+                continue;
+            }
+            info!(
+                "Undoing {} {}",
+                operation.action.as_str(),
+                operation.source.display()
+            );
+            let result = match (&operation.action, &operation.trash_id) {
+                (Action::Delete, Some(trash_id)) => match trash.get(trash_id) {
+                    Some(entry) => trash
+                        .restore(&entry)
+                        .map_err(|error| UndoError::Retry(error.to_string())),
+                    None => Err(UndoError::Unrecoverable(format!(
+                        "{} is no longer in the trash",
+                        trash_id
+                    ))),
+                },
+                (Action::Delete, None) => Err(UndoError::Unrecoverable(
+                    "deleted without trash".to_string(),
+                )),
+                (Action::Rename, _) => {
+                    if operation.source.exists() {
+                        Err(UndoError::Retry(format!(
+                            "{} already exists",
+                            operation.source.display()
+                        )))
+                    } else if !operation.destination.exists() {
+                        Err(UndoError::Unrecoverable(format!(
+                            "{} no longer exists",
+                            operation.destination.display()
+                        )))
+                    } else {
+                        std::fs::rename(&operation.destination, &operation.source)
+                            .map_err(|error| UndoError::Retry(error.to_string()))
+                    }
+                }
+            };
+            let message = match result {
+                Ok(()) => {
+                    operation.undo = UndoState::Undone;
+                    continue;
+                }
+                Err(UndoError::Retry(message)) => message,
+                Err(UndoError::Unrecoverable(message)) => {
+                    operation.undo = UndoState::Unrecoverable(message.clone());
+                    message
+                }
+            };
+            warn!(
+                "Undo {} {} failed. {}",
+                operation.action.as_str(),
+                operation.source.display(),
+                message
+            );
+            errors.push(format!("{}: {}", operation.source.display(), message));
+        }
+        if batch.pending().next().is_none() {
+            batch.undone_at = Some(unix_time());
+        }
+        self.save(&batches)?;
+        Ok(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFolder;
+
+    fn rename(song_folder: &Path, source: &str, destination: &str) -> JournalOperation {
+        JournalOperation {
+            action: Action::Rename,
+            source: song_folder.join(source),
+            destination: song_folder.join(destination),
+            trash_id: None,
+            timestamp: unix_time(),
+            outcome: Outcome::Succeeded,
+            undo: UndoState::Pending,
+        }
+    }
+
+    #[test]
+    fn undo_reverses_renames() {
+        let song_folder = TempFolder::new("undo");
+        std::fs::create_dir(song_folder.join("new")).unwrap();
+        let journal = Journal::new(&song_folder);
+        let mut batch = JournalBatch::new();
+        batch.operations.push(rename(&song_folder, "old", "new"));
+        journal.append(batch).unwrap();
+        assert!(journal.undo_last().unwrap().is_empty());
+        assert!(song_folder.join("old").is_dir());
+        assert!(journal.last_undoable().is_none());
+    }
+
+    #[test]
+    fn failed_operations_are_retried() {
+        let song_folder = TempFolder::new("retry");
+        std::fs::create_dir(song_folder.join("new")).unwrap();
+        std::fs::create_dir(song_folder.join("taken")).unwrap();
+        std::fs::create_dir(song_folder.join("other")).unwrap();
+        let journal = Journal::new(&song_folder);
+        let mut batch = JournalBatch::new();
+        batch.operations.push(rename(&song_folder, "old", "new"));
+        batch
+            .operations
+            .push(rename(&song_folder, "taken", "other"));
+        journal.append(batch).unwrap();
+        assert_eq!(journal.undo_last().unwrap().len(), 1);
+        assert!(song_folder.join("old").is_dir());
+        let batch = journal.last_undoable().unwrap();
+        assert_eq!(batch.pending().count(), 1);
+        std::fs::remove_dir(song_folder.join("taken")).unwrap();
+        assert!(journal.undo_last().unwrap().is_empty());
+        assert!(song_folder.join("taken").is_dir());
+        assert!(journal.last_undoable().is_none());
+    }
+
+    #[test]
+    fn unrecoverable_batch_does_not_block_older_ones() {
+        let song_folder = TempFolder::new("unrecoverable");
+        std::fs::create_dir(song_folder.join("new")).unwrap();
+        let journal = Journal::new(&song_folder);
+        let mut older = JournalBatch::new();
+        older.operations.push(rename(&song_folder, "old", "new"));
+        journal.append(older).unwrap();
+        let mut newer = JournalBatch::new();
+        newer.operations.push(rename(&song_folder, "other", "gone"));
+        journal.append(newer).unwrap();
+        assert_eq!(journal.undo_last().unwrap().len(), 1);
+        assert!(journal.undo_last().unwrap().is_empty());
+        assert!(song_folder.join("old").is_dir());
+        assert!(journal.last_undoable().is_none());
+    }
+
+    #[test]
+    fn only_pending_deletes_reference_the_trash() {
+        let song_folder = TempFolder::new("referenced");
+        let journal = Journal::new(&song_folder);
+        let mut batch = JournalBatch::new();
+        for (folder, undo) in [
+            ("kept", UndoState::Pending),
+            ("restored", UndoState::Undone),
+        ] {
+            let mut operation = rename(&song_folder, folder, folder);
+            operation.action = Action::Delete;
+            operation.trash_id = Some(folder.to_string());
+            operation.undo = undo;
+            batch.operations.push(operation);
+        }
+        journal.append(batch).unwrap();
+        assert_eq!(
+            journal.referenced_trash_ids(),
+            HashSet::from(["kept".to_string()])
+        );
+    }
+}
@@ -1,39 +1,83 @@
+//! Manage the custom song folders of Beat Saber.
+//!
+//! Besides the app, the crate can be used as a library: [`Library::scan`] loads the songs of a
+//! song folder, [`Song`] gives access to what their info.dat describes, [`ChangeSet`] renames
+//! and deletes song folders and [`Playlist`] reads and writes `.bplist` playlists.
+
 #[macro_use]
 extern crate rust_i18n;
 i18n!("locales");
 
 mod app;
+mod audio;
+mod beatmap;
+mod cleanup;
+pub mod cli;
+mod duplicate;
+mod error;
+mod filter;
+mod index;
+mod infodat;
+mod invalid;
+mod journal;
+mod library;
+mod naming;
+mod plan;
+mod playlist;
+mod snapshot;
+#[cfg(test)]
+mod test_util;
+mod trash;
+mod watcher;
 pub use app::ManagerApp;
-use deunicode::deunicode;
+use beatmap::BeatmapStats;
+pub use error::{Error, Result};
+use index::{file_stamps, LibraryIndex};
+use infodat::{
+    Contributor, DifficultyCustomData, InfoDat, InfoDatDifficultyBeatmap,
+    InfoDatDifficultyBeatmapSet, InfoDatV4, InfoDatV4DifficultyBeatmap, InfoDatVersion,
+};
+pub use invalid::InvalidReason;
+use journal::{unix_time, Journal, JournalBatch, JournalOperation, Outcome, UndoState};
+pub use library::{ChangeSet, Library, ScanOptions};
+use naming::NamingTemplate;
+use plan::ChangePlan;
+pub use playlist::{Playlist, PlaylistMatch, PlaylistSong};
+pub use snapshot::{Snapshot, SnapshotDiff};
+use trash::Trash;
 
 use log::{debug, error, info, warn};
 use native_tls::{TlsConnector, TlsStream};
-use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sha1::{Digest, Sha1};
 use std::collections::VecDeque;
 use std::fs::{read_dir, File};
 use std::io::{BufRead, BufReader, Write};
 use std::net::TcpStream;
-use std::sync::RwLock;
-use std::thread;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Mutex, RwLock};
+use std::thread::{self, JoinHandle};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     hash::{Hash, Hasher},
     io::Read,
     path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, SystemTime},
 };
 
-static CONCURRENT_THREADS_MAX: usize = 16;
-static CONCURRENT_THREADS_MIN: usize = 8;
+static DEFAULT_PARALLELISM: usize = 8;
 static DEFAULT_ID: &str = "00000";
+/// Folder inside the song folder for the data of the manager itself, never scanned as a song.
+static DATA_DIR_NAME: &str = ".bs_song_manager";
 static BEATSAVER_DOMAIN: &str = "api.beatsaver.com";
 static BEATSAVER_ADDR: &str = "api.beatsaver.com:443";
 static BEATSAVER_BATCH: usize = 40;
 
 fn get_api_connection() -> Result<TlsStream<TcpStream>, Box<dyn std::error::Error>> {
-    let connector = TlsConnector::new().unwrap();
+    let connector = TlsConnector::new()?;
     debug!("Connecting to {}...", BEATSAVER_ADDR);
     let stream = TcpStream::connect(BEATSAVER_ADDR)?;
     debug!("Connected to {}.", BEATSAVER_ADDR);
@@ -41,6 +85,7 @@ fn get_api_connection() -> Result<TlsStream<TcpStream>, Box<dyn std::error::Erro
     Ok(stream)
 }
 
+/// Build BeatSaver requests for `hashs`, each asking for at most `BEATSAVER_BATCH` of them.
 fn build_requests(mut hashs: VecDeque<String>) -> VecDeque<String> {
     let mut requests = VecDeque::new();
     let mut hash_buffer = Vec::new();
@@ -68,80 +113,154 @@ fn build_requests(mut hashs: VecDeque<String>) -> VecDeque<String> {
     requests
 }
 
-fn update_id_cache(mut hashs: VecDeque<String>, id_cache: &Arc<RwLock<HashMap<String, String>>>) {
+/// Look up the level ids of `hashs` on BeatSaver and add them to `id_cache`.
+///
+/// Not being able to connect stops the lookup, any other failed request doesn't stop the
+/// others and the first failure is returned once all of them have been tried.
+fn update_id_cache(
+    mut hashs: VecDeque<String>,
+    id_cache: &Arc<RwLock<HashMap<String, String>>>,
+    progress: &ScanProgress,
+) -> Result<()> {
     hashs.retain(|hash| !id_cache.read().unwrap().contains_key(hash));
+    progress.hashes_total.store(hashs.len(), Ordering::Relaxed);
     let mut request_list = build_requests(hashs);
-    debug!("Trying to get connection to api server.");
-    debug!("Get connection successful.");
-    while !request_list.is_empty() {
-        let mut stream = match get_api_connection() {
-            Ok(stream) => stream,
-            Err(error) => {
-                warn!("Failed to connect api server.{}", error);
-                return;
-            }
-        };
-        let request = request_list.pop_front().unwrap();
-        debug!("Sending request...\n{}", request);
-        if let Err(error) = stream.write_all(request.as_bytes()) {
-            warn!("Failed to send request to api server.{}", error);
-            continue;
+    let mut first_error = None;
+    while let Some(request) = request_list.pop_front() {
+        if progress.is_cancelled() {
+            break;
         }
-        debug!("Send request done. Waiting for response...");
-        let mut reader = BufReader::new(stream);
-        let mut bytes_to_read: usize = 0;
-        loop {
-            let mut buf = vec![];
-            if let Err(error) = reader.read_until(b'\n', &mut buf) {
-                warn!("Got error when reading HTTP head.{}", error);
-                break;
-            }
-            let head = String::from_utf8_lossy(&buf);
-            debug!("Read head from server: {}", head);
-            if head.starts_with("Content-Length:") {
-                bytes_to_read = match head.split(": ").nth(1) {
-                    Some(str) => match str.trim().parse::<usize>() {
-                        Ok(size) => size,
-                        Err(error) => {
-                            warn!("Failed to parse Content-Length.{}", error);
-                            break;
-                        }
-                    },
-                    None => {
-                        warn!("Failed to parse Content-Length.");
-                        break;
+        let stream = get_api_connection().map_err(|error| Error::Network(error.to_string()))?;
+        match request_level_ids(stream, &request) {
+            Ok(level_ids) => {
+                let mut id_cache = id_cache.write().unwrap();
+                let mut inserted = 0;
+                for (hash, id) in level_ids {
+                    debug!("Got level id {} for hash {}", id, hash);
+                    if id_cache.insert(hash, id).is_none() {
+                        inserted += 1;
                     }
-                };
-                debug!("bytes_to_read={}", bytes_to_read);
+                }
+                // Hashes BeatSaver doesn't know stay unresolved.
+                progress
+                    .hashes_resolved
+                    .fetch_add(inserted, Ordering::Relaxed);
             }
-            if head.trim().is_empty() {
-                break;
+            Err(error) => {
+                warn!("Request level ids failed. {}", error);
+                first_error.get_or_insert(Error::Network(error));
             }
         }
-        if bytes_to_read != 0 {
-            let mut resp = vec![0u8; bytes_to_read];
-            if let Err(error) = reader.read_exact(&mut resp) {
-                warn!("Got error when reading http body.{}", error);
-                continue;
-            }
-            let body = String::from_utf8_lossy(&resp);
-            debug!("API server return response.\n{}", body);
+    }
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
 
-            let content: Value = match serde_json::from_str(body.as_ref()) {
-                Ok(content) => content,
-                Err(error) => {
-                    warn!("Failed to parse json.{}", error);
-                    continue;
-                }
-            };
-            for (hash, value) in content.as_object().unwrap() {
-                let id = match value["id"].as_str() {
-                    Some(id) => id.to_string(),
-                    None => continue,
-                };
-                debug!("Got level id {} for hash {}", id, hash);
-                id_cache.write().unwrap().insert(hash.clone(), id);
-            }
+/// Send one request built by [`build_requests`] and return the level ids in the response,
+/// keyed by level hash.
+fn request_level_ids(
+    mut stream: TlsStream<TcpStream>,
+    request: &str,
+) -> Result<Vec<(String, String)>, String> {
+    debug!("Sending request...\n{}", request);
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|error| format!("send request failed: {}", error))?;
+    debug!("Send request done. Waiting for response...");
+    let mut reader = BufReader::new(stream);
+    let mut bytes_to_read: usize = 0;
+    loop {
+        let mut buf = vec![];
+        reader
+            .read_until(b'\n', &mut buf)
+            .map_err(|error| format!("read HTTP head failed: {}", error))?;
+        let head = String::from_utf8_lossy(&buf);
+        debug!("Read head from server: {}", head);
+        if head.starts_with("Content-Length:") {
+            bytes_to_read = head
+                .split(": ")
+                .nth(1)
+                .and_then(|size| size.trim().parse::<usize>().ok())
+                .ok_or_else(|| format!("invalid header {}", head.trim()))?;
+            debug!("bytes_to_read={}", bytes_to_read);
+        }
+        if head.trim().is_empty() {
+            break;
+        }
+    }
+    if bytes_to_read == 0 {
+        return Ok(Vec::new());
+    }
+    let mut resp = vec![0u8; bytes_to_read];
+    reader
+        .read_exact(&mut resp)
+        .map_err(|error| format!("read HTTP body failed: {}", error))?;
+    let body = String::from_utf8_lossy(&resp);
+    debug!("API server return response.\n{}", body);
+    let content: Value = serde_json::from_str(body.as_ref())
+        .map_err(|error| format!("parse response failed: {}", error))?;
+    let maps = content
+        .as_object()
+        .ok_or_else(|| format!("unexpected response {}", body))?;
+    Ok(maps
+        .iter()
+        .filter_map(|(hash, value)| Some((hash.clone(), value["id"].as_str()?.to_string())))
+        .collect())
+}
+
+/// Read a file that takes part in the level hash and append its content to `hash_data`.
+fn read_hash_file(path: &Path, hash_data: &mut Vec<u8>) -> Result<Vec<u8>, InvalidReason> {
+    match std::fs::read(path) {
+        Ok(data) => {
+            hash_data.extend(&data);
+            Ok(data)
+        }
+        Err(error) => {
+            error!("Read beatmap file {} failed. {}", path.display(), error);
+            let file = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            Err(match error.kind() {
+                std::io::ErrorKind::NotFound => InvalidReason::MissingFile(file),
+                kind => InvalidReason::HashReadFailed {
+                    file,
+                    kind,
+                    message: error.to_string(),
+                },
+            })
+        }
+    }
+}
+
+/// Total size of the files under `path`, entries that can't be read are left out.
+fn folder_size(path: &Path) -> u64 {
+    let entries = match read_dir(path) {
+        Ok(entries) => entries,
+        Err(error) => {
+            warn!("Read file list of {} failed. {}", path.display(), error);
+            return 0;
+        }
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => folder_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Count the objects of a difficulty file, a broken file only leaves the stats empty.
+fn read_beatmap_stats(path: &Path, data: &[u8]) -> BeatmapStats {
+    match BeatmapStats::from_data(data) {
+        Ok(stats) => stats,
+        Err(error) => {
+            warn!("Parse beatmap file {} failed. {}", path.display(), error);
+            BeatmapStats::default()
         }
     }
 }
@@ -153,7 +272,8 @@ fn hash_string(data: &Vec<u8>) -> String {
     hex::encode(result)
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 enum BeatmapCharacteristic {
     Degree360,
     Degree90,
@@ -162,9 +282,23 @@ enum BeatmapCharacteristic {
     OneSaber,
     Lawless,
     Lightshow,
+    /// Characteristic registered by SongCore or another mod, keeps the name from info.dat.
+    Custom(String),
 }
 impl BeatmapCharacteristic {
-    fn as_str(&self) -> &'static str {
+    fn from_name(name: &str) -> Self {
+        match name {
+            "360Degree" => BeatmapCharacteristic::Degree360,
+            "90Degree" => BeatmapCharacteristic::Degree90,
+            "Standard" => BeatmapCharacteristic::Standard,
+            "NoArrows" => BeatmapCharacteristic::NoArrows,
+            "OneSaber" => BeatmapCharacteristic::OneSaber,
+            "Lawless" => BeatmapCharacteristic::Lawless,
+            "Lightshow" => BeatmapCharacteristic::Lightshow,
+            _ => BeatmapCharacteristic::Custom(name.to_string()),
+        }
+    }
+    fn as_str(&self) -> &str {
         match self {
             BeatmapCharacteristic::Degree360 => "360Degree",
             BeatmapCharacteristic::Degree90 => "90Degree",
@@ -173,67 +307,165 @@ impl BeatmapCharacteristic {
             BeatmapCharacteristic::OneSaber => "OneSaber",
             BeatmapCharacteristic::Lawless => "Lawless",
             BeatmapCharacteristic::Lightshow => "Lightshow",
+            BeatmapCharacteristic::Custom(name) => name.as_str(),
         }
     }
 }
+impl From<String> for BeatmapCharacteristic {
+    fn from(name: String) -> Self {
+        BeatmapCharacteristic::from_name(&name)
+    }
+}
+impl From<BeatmapCharacteristic> for String {
+    fn from(characteristic: BeatmapCharacteristic) -> Self {
+        characteristic.as_str().to_string()
+    }
+}
 
-#[derive(Clone, PartialEq, Eq)]
-struct DifficultyBeatmap {
+/// One difficulty of a characteristic.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DifficultyBeatmap {
     difficulty: String,
     difficulty_rank: u64,
     beatmap_filename: String,
+    /// Only v4 maps keep lighting events in a separate file.
+    lightshow_filename: String,
+    note_jump_movement_speed: f64,
+    note_jump_start_beat_offset: f64,
+    custom_data: DifficultyCustomData,
+    stats: BeatmapStats,
 }
 impl DifficultyBeatmap {
-    pub fn new(data: &Value) -> Option<Self> {
-        Some(DifficultyBeatmap {
-            difficulty: data["_difficulty"].as_str()?.to_string(),
-            difficulty_rank: data["_difficultyRank"].as_u64()?,
-            beatmap_filename: data["_beatmapFilename"].as_str()?.to_string(),
-        })
+    pub(crate) fn new(data: &InfoDatDifficultyBeatmap) -> Self {
+        DifficultyBeatmap {
+            difficulty: data.difficulty.clone(),
+            difficulty_rank: data.difficulty_rank,
+            beatmap_filename: data.beatmap_filename.clone(),
+            lightshow_filename: String::new(),
+            note_jump_movement_speed: data.note_jump_movement_speed,
+            note_jump_start_beat_offset: data.note_jump_start_beat_offset,
+            custom_data: data.custom_data.clone(),
+            stats: BeatmapStats::default(),
+        }
+    }
+    pub(crate) fn from_v4(data: &InfoDatV4DifficultyBeatmap) -> Self {
+        // v4 dropped the rank field, use the rank the game assigns to each difficulty.
+        let difficulty_rank = match data.difficulty.as_str() {
+            "Easy" => 1,
+            "Normal" => 3,
+            "Hard" => 5,
+            "Expert" => 7,
+            "ExpertPlus" => 9,
+            _ => 0,
+        };
+        DifficultyBeatmap {
+            difficulty: data.difficulty.clone(),
+            difficulty_rank,
+            beatmap_filename: data.beatmap_data_filename.clone(),
+            lightshow_filename: data.lightshow_data_filename.clone(),
+            note_jump_movement_speed: data.note_jump_movement_speed,
+            note_jump_start_beat_offset: data.note_jump_start_beat_offset,
+            custom_data: data.custom_data.clone(),
+            stats: BeatmapStats::default(),
+        }
+    }
+    /// Difficulty name, like `Expert` or `ExpertPlus`.
+    pub fn difficulty(&self) -> &str {
+        &self.difficulty
+    }
+    /// Label shown in the game instead of the difficulty name, if the mapper set one.
+    pub fn label(&self) -> Option<&str> {
+        self.custom_data.difficulty_label.as_deref()
+    }
+    pub fn beatmap_filename(&self) -> &str {
+        &self.beatmap_filename
+    }
+    pub fn note_jump_movement_speed(&self) -> f64 {
+        self.note_jump_movement_speed
+    }
+    pub fn note_jump_start_beat_offset(&self) -> f64 {
+        self.note_jump_start_beat_offset
+    }
+    pub fn notes(&self) -> usize {
+        self.stats.notes
+    }
+    pub fn bombs(&self) -> usize {
+        self.stats.bombs
+    }
+    pub fn walls(&self) -> usize {
+        self.stats.walls
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
-struct DifficultyBeatmapSet {
+/// The difficulties of one characteristic, like Standard or OneSaber.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DifficultyBeatmapSet {
+    #[serde(rename = "characteristic")]
     beatmap_characteristic_name: BeatmapCharacteristic,
     difficulty_beatmaps: Vec<DifficultyBeatmap>,
 }
 impl DifficultyBeatmapSet {
-    pub fn new(data: &Value) -> Option<Self> {
-        let mut difficulty_beatmaps = Vec::new();
-        let beatmap_characteristic_name = match data["_beatmapCharacteristicName"].as_str()? {
-            "360Degree" => BeatmapCharacteristic::Degree360,
-            "90Degree" => BeatmapCharacteristic::Degree90,
-            "Standard" => BeatmapCharacteristic::Standard,
-            "NoArrows" => BeatmapCharacteristic::NoArrows,
-            "OneSaber" => BeatmapCharacteristic::OneSaber,
-            "Lawless" => BeatmapCharacteristic::Lawless,
-            "Lightshow" => BeatmapCharacteristic::Lightshow,
-            _ => return None,
-        };
-        for difficulty_beatmap in data["_difficultyBeatmaps"].as_array()? {
-            difficulty_beatmaps.push(DifficultyBeatmap::new(difficulty_beatmap)?)
+    pub(crate) fn new(data: &InfoDatDifficultyBeatmapSet) -> Self {
+        DifficultyBeatmapSet {
+            beatmap_characteristic_name: BeatmapCharacteristic::from_name(
+                &data.beatmap_characteristic_name,
+            ),
+            difficulty_beatmaps: data
+                .difficulty_beatmaps
+                .iter()
+                .map(DifficultyBeatmap::new)
+                .collect(),
         }
-        Some(DifficultyBeatmapSet {
-            beatmap_characteristic_name,
-            difficulty_beatmaps,
-        })
+    }
+    /// Characteristic name as written in info.dat, like `Standard` or `360Degree`.
+    pub fn characteristic(&self) -> &str {
+        self.beatmap_characteristic_name.as_str()
+    }
+    pub fn difficulties(&self) -> &[DifficultyBeatmap] {
+        &self.difficulty_beatmaps
     }
 }
 /// This struct should generate from info.dat
 ///
 /// Refer https://github.com/Kylemc1413/SongCore#infodat-explanation
-#[derive(Clone, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Song {
     song_folder_path: PathBuf,
+    info_dat_version: String,
     song_name: String,
     song_sub_name: String,
     song_author_name: String,
     level_author_name: String,
-    beats_per_minute: u64,
+    beats_per_minute: f64,
+    song_time_offset: f64,
+    shuffle: f64,
+    shuffle_period: f64,
+    preview_start_time: f64,
+    preview_duration: f64,
     song_filename: String,
+    /// BPM map file of a v4 info.dat.
+    audio_data_filename: String,
+    /// Length of the song file, `None` when it couldn't be read.
+    #[serde(with = "snapshot::seconds")]
+    duration: Option<Duration>,
+    /// Total size of all files in the song folder in bytes.
+    folder_size: u64,
+    /// Creation time of the song folder, or its modification time where that's unsupported.
+    #[serde(with = "snapshot::unix_seconds")]
+    date_added: Option<SystemTime>,
+    /// Modification time of the song folder.
+    #[serde(with = "snapshot::unix_seconds")]
+    date_modified: Option<SystemTime>,
     cover_image_filename: String,
+    environment_name: String,
+    all_directions_environment_name: String,
+    contributors: Vec<Contributor>,
     difficulty_beatmap_sets: Vec<DifficultyBeatmapSet>,
+    /// Issues that don't keep the song from loading.
+    problems: Vec<InvalidReason>,
     ///Refer https://github.com/Kylemc1413/SongCore/blob/master/Utilities/Hashing.cs#L173
     level_hash: String,
     level_id: String,
@@ -244,6 +476,7 @@ impl PartialEq for Song {
         self.song_folder_path == other.song_folder_path
     }
 }
+impl Eq for Song {}
 impl Hash for Song {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.song_folder_path.hash(state);
@@ -251,105 +484,395 @@ impl Hash for Song {
 }
 
 impl Song {
-    pub fn from_path(
+    /// Load the song folder at `path` on its own. The level id is only known to a scanned
+    /// [`Library`], so it is left unset.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let pending_hash_list = Arc::new(RwLock::new(VecDeque::new()));
+        let id_cache = Arc::new(RwLock::new(HashMap::new()));
+        Song::from_path(&path, &pending_hash_list, &id_cache)
+            .map_err(|reason| Error::from_reason(path, reason))
+    }
+    /// Folder the song was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.song_folder_path
+    }
+    /// Version of the info.dat format, like `2.0.0` or `4.0.0`.
+    pub fn info_dat_version(&self) -> &str {
+        &self.info_dat_version
+    }
+    pub fn name(&self) -> &str {
+        &self.song_name
+    }
+    pub fn sub_name(&self) -> &str {
+        &self.song_sub_name
+    }
+    pub fn song_author(&self) -> &str {
+        &self.song_author_name
+    }
+    /// Level author, the mappers joined by `, ` for v4 maps.
+    pub fn mapper(&self) -> &str {
+        &self.level_author_name
+    }
+    pub fn bpm(&self) -> f64 {
+        self.beats_per_minute
+    }
+    /// Length of the song file, `None` when it couldn't be read.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+    /// Total size of all files in the song folder in bytes.
+    pub fn folder_size(&self) -> u64 {
+        self.folder_size
+    }
+    pub fn song_filename(&self) -> &str {
+        &self.song_filename
+    }
+    pub fn cover_image_filename(&self) -> &str {
+        &self.cover_image_filename
+    }
+    pub fn difficulty_sets(&self) -> &[DifficultyBeatmapSet] {
+        &self.difficulty_beatmap_sets
+    }
+    /// Issues that don't keep the song from loading, like a missing cover.
+    pub fn problems(&self) -> &[InvalidReason] {
+        &self.problems
+    }
+    /// SongCore level hash in lowercase hex.
+    pub fn level_hash(&self) -> &str {
+        &self.level_hash
+    }
+    /// BeatSaver id, `None` when the map isn't known to BeatSaver.
+    pub fn level_id(&self) -> Option<&str> {
+        if self.level_id == DEFAULT_ID {
+            None
+        } else {
+            Some(&self.level_id)
+        }
+    }
+    pub(crate) fn from_path(
         song_path: &PathBuf,
         pending_hash_list: &Arc<RwLock<VecDeque<String>>>,
         id_cache: &Arc<RwLock<HashMap<String, String>>>,
-    ) -> Option<Self> {
+    ) -> Result<Self, InvalidReason> {
         let file_list = read_dir(song_path);
         let file_list = match file_list {
             Ok(entry) => entry,
             Err(error) => {
                 error!("Read file list failed. {}", error);
-                return None;
+                return Err(InvalidReason::read_failed("", &error));
             }
         };
-        let mut hash_data: Vec<u8> = Vec::new();
         for entry in file_list.flatten() {
             if !entry.path().is_file() || !entry.file_name().eq_ignore_ascii_case("info.dat") {
                 continue;
             }
+            let infodat_name = entry.file_name().to_string_lossy().to_string();
             let infodat_file = File::open(entry.path());
             let mut infodat_file = match infodat_file {
                 Ok(file) => file,
                 Err(error) => {
                     error!("Read info.dat failed. {}", error);
-                    return None;
+                    return Err(InvalidReason::read_failed(infodat_name, &error));
                 }
             };
             let mut buffer = String::new();
             if let Err(error) = infodat_file.read_to_string(&mut buffer) {
                 error!("Read info.dat failed. {}", error);
-                return None;
+                return Err(InvalidReason::read_failed(infodat_name, &error));
             };
-            hash_data.extend(buffer.as_bytes());
-            let infodat: Result<Value, serde_json::Error> = serde_json::from_str(&buffer);
-            let infodat = match infodat {
-                Ok(infodat) => infodat,
+            let version: InfoDatVersion = match serde_json::from_str(&buffer) {
+                Ok(version) => version,
                 Err(error) => {
                     error!("Read info.dat failed. {}", error);
-                    return None;
+                    return Err(InvalidReason::from_json(infodat_name, &error));
                 }
             };
-            let mut difficulty_beatmap_sets = Vec::new();
-            for difficulty_beatmap_set in infodat["_difficultyBeatmapSets"].as_array()? {
-                let data = match DifficultyBeatmapSet::new(difficulty_beatmap_set) {
-                    Some(data) => data,
-                    None => return None,
-                };
-                for beatmap in &data.difficulty_beatmaps {
-                    let mut beatmap_file_path = song_path.clone();
-                    beatmap_file_path.push(beatmap.beatmap_filename.clone());
-                    let beatmap_file = File::open(beatmap_file_path);
-                    let mut beatmap_file = match beatmap_file {
-                        Ok(file) => file,
+            let mut hash_data: Vec<u8> = buffer.as_bytes().to_vec();
+            let mut result = match version.major() {
+                4 => {
+                    let infodat = match serde_json::from_str(&buffer) {
+                        Ok(infodat) => infodat,
                         Err(error) => {
-                            error!("Read beatmap file failed. {}", error);
-                            return None;
+                            error!("Read info.dat failed. {}", error);
+                            return Err(InvalidReason::from_json(infodat_name, &error));
                         }
                     };
-                    let mut buffer = String::new();
-                    if let Err(error) = beatmap_file.read_to_string(&mut buffer) {
-                        error!("Read beatmap file failed. {}", error);
-                        return None;
-                    }
-                    hash_data.extend(buffer.as_bytes());
+                    Song::from_infodat_v4(song_path, infodat, &mut hash_data)?
+                }
+                _ => {
+                    let infodat = match serde_json::from_str(&buffer) {
+                        Ok(infodat) => infodat,
+                        Err(error) => {
+                            error!("Read info.dat failed. {}", error);
+                            return Err(InvalidReason::from_json(infodat_name, &error));
+                        }
+                    };
+                    Song::from_infodat(song_path, infodat, &mut hash_data)?
+                }
+            };
+            result.info_dat_version = version.as_str().to_string();
+            result.folder_size = folder_size(song_path);
+            match std::fs::metadata(song_path) {
+                Ok(metadata) => {
+                    result.date_added = metadata.created().or_else(|_| metadata.modified()).ok();
+                    result.date_modified = metadata.modified().ok();
+                }
+                Err(error) => {
+                    warn!("Read metadata of {} failed. {}", song_path.display(), error);
                 }
-                difficulty_beatmap_sets.push(data);
             }
-            let level_hash = hash_string(&hash_data);
-            let level_id = match id_cache.write() {
-                Ok(id_cache) => match id_cache.get(&level_hash) {
-                    Some(id) => id.clone(),
-                    None => {
-                        pending_hash_list
-                            .write()
-                            .unwrap()
-                            .push_back(level_hash.clone());
-                        DEFAULT_ID.to_string()
-                    }
-                },
+            let song_file_path = song_path.join(&result.song_filename);
+            if !song_file_path.is_file() {
+                error!("Song file {} not found.", song_file_path.display());
+                return Err(InvalidReason::MissingAudio(result.song_filename));
+            }
+            if !song_path.join(&result.cover_image_filename).is_file() {
+                result.problems.push(InvalidReason::MissingCover(
+                    result.cover_image_filename.clone(),
+                ));
+            }
+            for set in &result.difficulty_beatmap_sets {
+                if let BeatmapCharacteristic::Custom(name) = &set.beatmap_characteristic_name {
+                    result
+                        .problems
+                        .push(InvalidReason::UnknownCharacteristic(name.clone()));
+                }
+            }
+            result.duration = match audio::ogg_duration(&song_file_path) {
+                Ok(duration) => Some(duration),
                 Err(error) => {
-                    warn!("Failed to get cache lock.{}", error);
-                    DEFAULT_ID.to_string()
+                    warn!(
+                        "Read duration of {} failed. {}",
+                        song_file_path.display(),
+                        error
+                    );
+                    None
                 }
             };
-            let result = Song {
-                song_folder_path: song_path.to_path_buf(),
-                song_name: infodat["_songName"].as_str()?.to_string(),
-                song_sub_name: infodat["_songSubName"].as_str()?.to_string(),
-                song_author_name: infodat["_songAuthorName"].as_str()?.to_string(),
-                level_author_name: infodat["_levelAuthorName"].as_str()?.to_string(),
-                beats_per_minute: infodat["_beatsPerMinute"].as_u64()?,
-                song_filename: infodat["_songFilename"].as_str()?.to_string(),
-                cover_image_filename: infodat["_coverImageFilename"].as_str()?.to_string(),
-                difficulty_beatmap_sets,
-                level_hash,
-                level_id,
-            };
-            return Some(result);
+            result.level_hash = hash_string(&hash_data);
+            result.resolve_level_id(pending_hash_list, id_cache);
+            return Ok(result);
         }
-        None
+        Err(InvalidReason::NoInfoDat)
+    }
+    /// Take the level id from `id_cache`, or queue the level hash to be looked up on BeatSaver.
+    fn resolve_level_id(
+        &mut self,
+        pending_hash_list: &Arc<RwLock<VecDeque<String>>>,
+        id_cache: &Arc<RwLock<HashMap<String, String>>>,
+    ) {
+        self.level_id = match id_cache.write() {
+            Ok(id_cache) => match id_cache.get(&self.level_hash) {
+                Some(id) => id.clone(),
+                None => {
+                    pending_hash_list
+                        .write()
+                        .unwrap()
+                        .push_back(self.level_hash.clone());
+                    DEFAULT_ID.to_string()
+                }
+            },
+            Err(error) => {
+                warn!("Failed to get cache lock.{}", error);
+                DEFAULT_ID.to_string()
+            }
+        };
+    }
+    /// Stand-in for an entry that couldn't be loaded, so that it can still be queued for
+    /// deletion. Only the path and the name are set.
+    fn placeholder(song_path: &Path) -> Self {
+        Song {
+            song_folder_path: song_path.to_path_buf(),
+            info_dat_version: String::new(),
+            song_name: song_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            song_sub_name: String::new(),
+            song_author_name: String::new(),
+            level_author_name: String::new(),
+            beats_per_minute: 0.0,
+            song_time_offset: 0.0,
+            shuffle: 0.0,
+            shuffle_period: 0.0,
+            preview_start_time: 0.0,
+            preview_duration: 0.0,
+            song_filename: String::new(),
+            audio_data_filename: String::new(),
+            duration: None,
+            folder_size: 0,
+            date_added: None,
+            date_modified: None,
+            cover_image_filename: String::new(),
+            environment_name: String::new(),
+            all_directions_environment_name: String::new(),
+            contributors: Vec::new(),
+            difficulty_beatmap_sets: Vec::new(),
+            problems: Vec::new(),
+            level_hash: String::new(),
+            level_id: DEFAULT_ID.to_string(),
+        }
+    }
+    /// Build a song from a v2 info.dat, appending the difficulty files to `hash_data`.
+    ///
+    /// Refer https://github.com/Kylemc1413/SongCore/blob/master/Utilities/Hashing.cs#L173
+    fn from_infodat(
+        song_path: &Path,
+        infodat: InfoDat,
+        hash_data: &mut Vec<u8>,
+    ) -> Result<Self, InvalidReason> {
+        let mut difficulty_beatmap_sets = Vec::new();
+        for difficulty_beatmap_set in &infodat.difficulty_beatmap_sets {
+            let mut data = DifficultyBeatmapSet::new(difficulty_beatmap_set);
+            for beatmap in &mut data.difficulty_beatmaps {
+                let beatmap_file_path = song_path.join(&beatmap.beatmap_filename);
+                let beatmap_data = read_hash_file(&beatmap_file_path, hash_data)?;
+                beatmap.stats = read_beatmap_stats(&beatmap_file_path, &beatmap_data);
+            }
+            difficulty_beatmap_sets.push(data);
+        }
+        Ok(Song {
+            song_folder_path: song_path.to_path_buf(),
+            info_dat_version: String::new(),
+            song_name: infodat.song_name,
+            song_sub_name: infodat.song_sub_name,
+            song_author_name: infodat.song_author_name,
+            level_author_name: infodat.level_author_name,
+            beats_per_minute: infodat.beats_per_minute,
+            song_time_offset: infodat.song_time_offset,
+            shuffle: infodat.shuffle,
+            shuffle_period: infodat.shuffle_period,
+            preview_start_time: infodat.preview_start_time,
+            preview_duration: infodat.preview_duration,
+            song_filename: infodat.song_filename,
+            audio_data_filename: String::new(),
+            duration: None,
+            folder_size: 0,
+            date_added: None,
+            date_modified: None,
+            cover_image_filename: infodat.cover_image_filename,
+            environment_name: infodat.environment_name,
+            all_directions_environment_name: infodat.all_directions_environment_name,
+            contributors: infodat.custom_data.contributors,
+            difficulty_beatmap_sets,
+            level_hash: String::new(),
+            level_id: DEFAULT_ID.to_string(),
+            problems: Vec::new(),
+        })
+    }
+    /// Build a song from a v4 Info.dat, appending the audio data, beatmap and lightshow files
+    /// to `hash_data` in the order SongCore hashes them.
+    fn from_infodat_v4(
+        song_path: &Path,
+        infodat: InfoDatV4,
+        hash_data: &mut Vec<u8>,
+    ) -> Result<Self, InvalidReason> {
+        if !infodat.audio.audio_data_filename.is_empty() {
+            read_hash_file(
+                &song_path.join(&infodat.audio.audio_data_filename),
+                hash_data,
+            )?;
+        }
+        let mut difficulty_beatmap_sets: Vec<DifficultyBeatmapSet> = Vec::new();
+        let mut mappers: Vec<String> = Vec::new();
+        let mut contributors = infodat.custom_data.contributors;
+        for difficulty_beatmap in &infodat.difficulty_beatmaps {
+            let mut beatmap = DifficultyBeatmap::from_v4(difficulty_beatmap);
+            let beatmap_file_path = song_path.join(&beatmap.beatmap_filename);
+            let beatmap_data = read_hash_file(&beatmap_file_path, hash_data)?;
+            beatmap.stats = read_beatmap_stats(&beatmap_file_path, &beatmap_data);
+            if !beatmap.lightshow_filename.is_empty() {
+                let lightshow_file_path = song_path.join(&beatmap.lightshow_filename);
+                let lightshow_data = read_hash_file(&lightshow_file_path, hash_data)?;
+                beatmap.stats.events =
+                    read_beatmap_stats(&lightshow_file_path, &lightshow_data).events;
+            }
+            let authors = &difficulty_beatmap.beatmap_authors;
+            for mapper in &authors.mappers {
+                if !mappers.contains(mapper) {
+                    mappers.push(mapper.clone());
+                }
+            }
+            for lighter in &authors.lighters {
+                if !contributors
+                    .iter()
+                    .any(|contributor| &contributor.name == lighter)
+                {
+                    contributors.push(Contributor {
+                        role: "Lighter".to_string(),
+                        name: lighter.clone(),
+                        icon_path: String::new(),
+                    });
+                }
+            }
+            let characteristic =
+                BeatmapCharacteristic::from_name(&difficulty_beatmap.characteristic);
+            match difficulty_beatmap_sets
+                .iter_mut()
+                .find(|set| set.beatmap_characteristic_name == characteristic)
+            {
+                Some(set) => set.difficulty_beatmaps.push(beatmap),
+                None => difficulty_beatmap_sets.push(DifficultyBeatmapSet {
+                    beatmap_characteristic_name: characteristic,
+                    difficulty_beatmaps: vec![beatmap],
+                }),
+            }
+        }
+        let environment_name = infodat
+            .difficulty_beatmaps
+            .first()
+            .and_then(|beatmap| infodat.environment_names.get(beatmap.environment_name_idx))
+            .or_else(|| infodat.environment_names.first())
+            .cloned()
+            .unwrap_or_default();
+        Ok(Song {
+            song_folder_path: song_path.to_path_buf(),
+            info_dat_version: String::new(),
+            song_name: infodat.song.title,
+            song_sub_name: infodat.song.sub_title,
+            song_author_name: infodat.song.author,
+            level_author_name: mappers.join(", "),
+            beats_per_minute: infodat.audio.bpm,
+            song_time_offset: 0.0,
+            shuffle: 0.0,
+            shuffle_period: 0.0,
+            preview_start_time: infodat.audio.preview_start_time,
+            preview_duration: infodat.audio.preview_duration,
+            song_filename: infodat.audio.song_filename,
+            audio_data_filename: infodat.audio.audio_data_filename,
+            duration: None,
+            folder_size: 0,
+            date_added: None,
+            date_modified: None,
+            cover_image_filename: infodat.cover_image_filename,
+            environment_name,
+            all_directions_environment_name: String::new(),
+            contributors,
+            difficulty_beatmap_sets,
+            level_hash: String::new(),
+            level_id: DEFAULT_ID.to_string(),
+            problems: Vec::new(),
+        })
+    }
+    /// Highest difficulty rank over all characteristics.
+    fn highest_difficulty(&self) -> Option<&DifficultyBeatmap> {
+        self.difficulty_beatmap_sets
+            .iter()
+            .flat_map(|set| set.difficulty_beatmaps.iter())
+            .max_by_key(|beatmap| beatmap.difficulty_rank)
+    }
+    fn has_characteristic(&self, characteristic: &BeatmapCharacteristic) -> bool {
+        self.difficulty_beatmap_sets
+            .iter()
+            .any(|set| &set.beatmap_characteristic_name == characteristic)
+    }
+    fn has_difficulty(&self, difficulty: &str) -> bool {
+        self.difficulty_beatmap_sets.iter().any(|set| {
+            set.difficulty_beatmaps
+                .iter()
+                .any(|beatmap| beatmap.difficulty == difficulty)
+        })
     }
     fn read_cover_image(&self) -> Option<Vec<u8>> {
         let mut cover_image_path = self.song_folder_path.clone();
@@ -381,56 +904,196 @@ impl Song {
             }
         }
     }
-    /// The canonical naming of the folder refers to the naming method of the song package shared by WGzeyu(https://bs.wgzeyu.com/).
-    fn get_canonical_name(&self) -> String {
-        let name = deunicode(self.song_name.as_str());
-        let author = deunicode(self.level_author_name.as_str());
-        let regex = Regex::new(r#"[~#"%&*:<>?/\\{|}]+"#).unwrap();
-        regex
-            .replace_all(
-                format!("{} ({} - {})", &self.level_id, name, author).as_str(),
-                "_",
-            )
-            .to_string()
+    /// Name of the folder following `naming`.
+    fn get_canonical_name(&self, naming: &NamingTemplate) -> String {
+        naming.render(self)
+    }
+    /// Whether the song folder is already named canonically.
+    fn has_canonical_name(&self, naming: &NamingTemplate) -> bool {
+        self.song_folder_path.file_name() == Some(self.get_canonical_name(naming).as_ref())
+    }
+}
+
+/// Messages streamed from a running scan.
+///
+/// Every song folder found in the scan gets exactly one `Song`, `Invalid` or `Skipped` message.
+enum ScanMessage {
+    Song(Box<Song>),
+    Invalid(PathBuf, InvalidReason),
+    /// The folder was not loaded because the scan got cancelled.
+    Skipped(PathBuf),
+    /// Level ids known after querying BeatSaver, keyed by level hash.
+    LevelIds(HashMap<String, String>),
+    /// Some level ids couldn't be looked up on BeatSaver.
+    LookupFailed(Error),
+}
+
+/// Progress of a running scan, shared between the scan threads and the UI.
+#[derive(Default)]
+struct ScanProgress {
+    folders_total: AtomicUsize,
+    folders_scanned: AtomicUsize,
+    hashes_total: AtomicUsize,
+    hashes_resolved: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl ScanProgress {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// A scan of the song folder running on a background thread.
+struct ScanTask {
+    progress: Arc<ScanProgress>,
+    receiver: Receiver<ScanMessage>,
+    handle: JoinHandle<()>,
+}
+
+impl ScanTask {
+    /// Start scanning `song_path` as `options` say.
+    fn start(song_path: PathBuf, options: ScanOptions) -> Self {
+        let progress = Arc::new(ScanProgress::default());
+        let (sender, receiver) = channel();
+        let progress_cloned = progress.clone();
+        let handle = thread::spawn(move || {
+            generate_song_list(&song_path, &options, &progress_cloned, &sender);
+        });
+        ScanTask {
+            progress,
+            receiver,
+            handle,
+        }
+    }
+    fn cancel(&self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+    fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+/// Everything a finished scan found.
+struct ScanResult {
+    /// Songs ordered by folder path.
+    songs: Vec<Song>,
+    invalid: Vec<(PathBuf, InvalidReason)>,
+    /// Why the level ids of some songs are unknown.
+    lookup_error: Option<Error>,
+}
+
+/// Scan `song_path` on the current thread, returning the songs with their level ids resolved
+/// and the entries that couldn't be loaded.
+fn scan_songs(song_path: &Path, options: &ScanOptions) -> ScanResult {
+    let progress = Arc::new(ScanProgress::default());
+    let (sender, receiver) = channel();
+    generate_song_list(song_path, options, &progress, &sender);
+    drop(sender);
+    let mut songs = Vec::new();
+    let mut invalid = Vec::new();
+    let mut lookup_error = None;
+    for message in receiver {
+        match message {
+            ScanMessage::Song(song) => songs.push(*song),
+            ScanMessage::Invalid(path, reason) => invalid.push((path, reason)),
+            ScanMessage::Skipped(path) => warn!("Skipped loading {}.", path.display()),
+            ScanMessage::LevelIds(level_ids) => {
+                for song in songs.iter_mut() {
+                    if let Some(id) = level_ids.get(&song.level_hash) {
+                        if song.level_id == DEFAULT_ID {
+                            song.level_id = id.clone();
+                        }
+                    }
+                }
+            }
+            ScanMessage::LookupFailed(error) => lookup_error = Some(error),
+        }
+    }
+    songs.sort_by(|a, b| a.song_folder_path.cmp(&b.song_folder_path));
+    invalid.sort_by(|a, b| a.0.cmp(&b.0));
+    ScanResult {
+        songs,
+        invalid,
+        lookup_error,
     }
 }
 
-fn generate_song_list(song_path: &Path) -> (Vec<Song>, HashSet<PathBuf>) {
-    let mut song_list = Vec::new();
-    let mut invalid_path = HashSet::new();
+/// Number of worker threads used to load song folders when not configured otherwise.
+fn default_parallelism() -> usize {
+    thread::available_parallelism()
+        .map(|parallelism| parallelism.get())
+        .unwrap_or(DEFAULT_PARALLELISM)
+}
+
+/// Level ids of `song_path` looked up on BeatSaver before, keyed by level hash.
+fn load_id_cache(song_path: &Path) -> HashMap<String, String> {
+    match std::fs::File::open(song_path.join("id.cache")) {
+        Ok(cache_id_file) => match serde_json::from_reader(cache_id_file) {
+            Ok(data) => data,
+            Err(error) => {
+                warn!("Parse id cache failed. {}", error);
+                HashMap::new()
+            }
+        },
+        Err(error) => {
+            warn!("Load id cache failed.{}", error);
+            HashMap::new()
+        }
+    }
+}
+
+/// Held while id.cache is read and written again, the watcher saves it while a scan may be
+/// running.
+static ID_CACHE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Add `id_cache` to the level ids saved in `song_path`, ids saved by someone else since they
+/// were loaded are kept.
+fn save_id_cache(song_path: &Path, id_cache: &HashMap<String, String>) {
+    let _guard = ID_CACHE_LOCK
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+    let mut merged = load_id_cache(song_path);
+    merged.extend(id_cache.iter().map(|(hash, id)| (hash.clone(), id.clone())));
+    match std::fs::File::create(song_path.join("id.cache")) {
+        Ok(cache_id_file) => {
+            if let Err(error) = serde_json::to_writer(cache_id_file, &merged) {
+                warn!("Save id cache failed.{}", error);
+            }
+        }
+        Err(error) => {
+            warn!("Save id cache failed.{}", error);
+        }
+    };
+}
+
+/// Load every song folder of `song_path`. Folders whose files are unchanged since the last scan
+/// are taken from the library index unless `options` ask for a full rescan.
+fn generate_song_list(
+    song_path: &Path,
+    options: &ScanOptions,
+    progress: &Arc<ScanProgress>,
+    sender: &Sender<ScanMessage>,
+) {
     let song_path_entry = read_dir(song_path);
     let song_path_entry = match song_path_entry {
         Ok(entry) => entry,
         Err(error) => {
             error!("Load song path failed. {}", error);
-            return (song_list, invalid_path);
+            return;
         }
     };
-    let shared_song_list = Arc::new(RwLock::new(Vec::new()));
-    let shared_invalid_path = Arc::new(RwLock::new(HashSet::new()));
     let pending_hash_list = Arc::new(RwLock::new(VecDeque::new()));
-    let cached_id = Arc::new(RwLock::new(HashMap::new()));
-    let mut task_list = Vec::new();
+    let cached_id = Arc::new(RwLock::new(load_id_cache(song_path)));
 
-    let mut cache_id_file = PathBuf::new();
-    cache_id_file.push(song_path);
-    cache_id_file.push("id.cache");
-    match std::fs::File::open(cache_id_file.as_path()) {
-        Ok(cache_id_file) => {
-            match serde_json::from_reader(cache_id_file) {
-                Ok(data) => {
-                    *cached_id.write().unwrap() = data;
-                }
-                Err(error) => {
-                    warn!("Parse id cache failed. {}", error);
-                }
-            };
-        }
-        Err(error) => {
-            warn!("Load id cache failed.{}", error);
-        }
+    let mut index = if options.full_rescan {
+        LibraryIndex::default()
+    } else {
+        LibraryIndex::load(song_path)
     };
+    let updated_index = Mutex::new(LibraryIndex::default());
 
+    let mut song_folders = VecDeque::new();
     for entry in song_path_entry {
         let entry = match entry {
             Ok(entry) => entry,
@@ -440,90 +1103,99 @@ fn generate_song_list(song_path: &Path) -> (Vec<Song>, HashSet<PathBuf>) {
             }
         };
         let song_folder_path = entry.path();
+        if song_folder_path.ends_with(DATA_DIR_NAME) {
+            continue;
+        }
         if song_folder_path.is_dir() {
-            let pending_hash_list_cloned = pending_hash_list.clone();
-            let cache_id_cloned = cached_id.clone();
-            let shared_song_list_cloned = shared_song_list.clone();
-            let shared_invalid_path_cloned = shared_invalid_path.clone();
-            let task = move || {
-                debug!(
-                    "Loading song from {}.",
-                    &song_folder_path.as_path().display()
-                );
-                if let Some(song) = Song::from_path(
-                    &song_folder_path,
-                    &pending_hash_list_cloned,
-                    &cache_id_cloned,
-                ) {
-                    shared_song_list_cloned.write().unwrap().push(song);
-                } else {
-                    shared_invalid_path_cloned
-                        .write()
-                        .unwrap()
-                        .insert(song_folder_path);
-                }
-            };
-            task_list.push(task);
+            song_folders.push_back(song_folder_path);
         } else if !song_folder_path.ends_with("id.cache") {
             warn!(
                 "Entry {} is not a directory.",
                 song_folder_path.as_path().display()
             );
-            invalid_path.insert(song_folder_path);
+            let _ = sender.send(ScanMessage::Invalid(
+                song_folder_path,
+                InvalidReason::NotADirectory,
+            ));
         }
     }
+    progress
+        .folders_total
+        .store(song_folders.len(), Ordering::Relaxed);
 
-    let mut task_pending = VecDeque::new();
-    for task in task_list {
-        if task_pending.len() < CONCURRENT_THREADS_MAX {
-            let task = thread::spawn(task);
-            task_pending.push_back(task);
-        } else {
-            while task_pending.len() > CONCURRENT_THREADS_MIN {
-                task_pending.pop_front().unwrap().join().unwrap();
-            }
+    // Workers take folders from the shared queue until it runs dry, so each folder is popped
+    // by exactly one of them.
+    let song_folders = Mutex::new(song_folders);
+    thread::scope(|scope| {
+        let song_folders = &song_folders;
+        let index = &index;
+        let updated_index = &updated_index;
+        let pending_hash_list = &pending_hash_list;
+        let cached_id = &cached_id;
+        for _ in 0..options.parallelism.max(1) {
+            // `Sender` is only `Sync` since Rust 1.72, every worker gets its own.
+            let sender = sender.clone();
+            scope.spawn(move || {
+                while !progress.is_cancelled() {
+                    let song_folder_path = match song_folders.lock().unwrap().pop_front() {
+                        Some(song_folder_path) => song_folder_path,
+                        None => break,
+                    };
+                    debug!(
+                        "Loading song from {}.",
+                        &song_folder_path.as_path().display()
+                    );
+                    let files = file_stamps(&song_folder_path);
+                    let song = match index.get(&song_folder_path, &files) {
+                        Some(Ok(song)) => {
+                            let mut song = song.clone();
+                            song.resolve_level_id(pending_hash_list, cached_id);
+                            Ok(song)
+                        }
+                        Some(Err(reason)) => Err(reason.clone()),
+                        None => Song::from_path(&song_folder_path, pending_hash_list, cached_id),
+                    };
+                    updated_index
+                        .lock()
+                        .unwrap()
+                        .insert(&song_folder_path, files, song.as_ref());
+                    let message = match song {
+                        Ok(song) => ScanMessage::Song(Box::new(song)),
+                        Err(reason) => ScanMessage::Invalid(song_folder_path, reason),
+                    };
+                    progress.folders_scanned.fetch_add(1, Ordering::Relaxed);
+                    // The receiver is gone when the scan has been abandoned.
+                    let _ = sender.send(message);
+                }
+            });
         }
+    });
+    let mut updated_index = updated_index.into_inner().unwrap();
+    for song_folder_path in song_folders.into_inner().unwrap() {
+        updated_index.keep(&mut index, &song_folder_path);
+        let _ = sender.send(ScanMessage::Skipped(song_folder_path));
     }
-    if !task_pending.is_empty() {
-        for task in task_pending {
-            task.join().unwrap();
+    if options.update_index {
+        if let Err(error) = updated_index.save(song_path) {
+            warn!("Save library index failed. {}", error);
         }
     }
 
-    if !pending_hash_list.read().unwrap().is_empty() {
-        update_id_cache(pending_hash_list.read().unwrap().clone(), &cached_id);
-    }
-    for mut song in shared_song_list.read().unwrap().clone() {
-        if song.level_id == DEFAULT_ID {
-            song.level_id = cached_id
-                .read()
-                .unwrap()
-                .clone()
-                .get(&song.level_hash)
-                .unwrap_or(&DEFAULT_ID.to_string())
-                .clone();
+    if !progress.is_cancelled() && !pending_hash_list.read().unwrap().is_empty() {
+        if let Err(error) = update_id_cache(
+            pending_hash_list.read().unwrap().clone(),
+            &cached_id,
+            progress,
+        ) {
+            warn!("Failed to connect api server.{}", error);
+            let _ = sender.send(ScanMessage::LookupFailed(error));
         }
-        song_list.push(song);
     }
-
-    song_list.sort_by(|a, b| a.song_name.cmp(&b.song_name));
-    invalid_path.extend(shared_invalid_path.read().unwrap().clone());
-
-    match std::fs::File::create(cache_id_file.as_path()) {
-        Ok(cache_id_file) => {
-            let id_cache = &*cached_id.read().unwrap();
-            if let Err(error) = serde_json::to_writer(cache_id_file, id_cache) {
-                warn!("Save id cache failed.{}", error);
-            }
-        }
-        Err(error) => {
-            warn!("Save id cache failed.{}", error);
-        }
-    };
-    (song_list, invalid_path)
+    let _ = sender.send(ScanMessage::LevelIds(cached_id.read().unwrap().clone()));
+    save_id_cache(song_path, &cached_id.read().unwrap());
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 enum Action {
     Delete,
     Rename,
@@ -538,35 +1210,90 @@ impl Action {
     }
 }
 
-fn apply_changes(pending_changes: &HashMap<Song, Action>) {
-    for (song, action) in pending_changes {
-        if let Err(error) = match action {
-            Action::Delete => {
-                info!("Deleting {}", song.song_folder_path.as_path().display());
-                std::fs::remove_dir_all(song.song_folder_path.as_path())
-            }
-            Action::Rename => {
-                if let Some(dst) = song.song_folder_path.parent() {
-                    let mut dst = PathBuf::from(dst);
-                    dst.push(song.get_canonical_name());
+/// Why a change couldn't be applied.
+struct ChangeError {
+    kind: std::io::ErrorKind,
+    message: String,
+}
+
+impl ChangeError {
+    fn new(error: &(dyn std::error::Error + 'static)) -> Self {
+        ChangeError {
+            kind: match error.downcast_ref::<std::io::Error>() {
+                Some(error) => error.kind(),
+                None => std::io::ErrorKind::Other,
+            },
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Result of applying the change of one song, with the folder it ends up in on success.
+struct ChangeResult {
+    song: Song,
+    action: Action,
+    outcome: Result<PathBuf, ChangeError>,
+}
+
+/// Apply the planned changes of the library in `song_folder` and record them in its journal.
+fn apply_changes(song_folder: &Path, plan: &ChangePlan) -> Vec<ChangeResult> {
+    let trash = Trash::new(song_folder);
+    let mut batch = JournalBatch::new();
+    let mut results = Vec::new();
+    for change in plan.applied() {
+        let song = &change.song;
+        let action = &change.action;
+        let source = song.song_folder_path.clone();
+        let mut trash_id = None;
+        let result: Result<PathBuf, Box<dyn std::error::Error>> = match action {
+            Action::Delete => trash.put(song).map(|entry| {
+                let destination = trash.entry_path(&entry);
+                trash_id = Some(entry.id);
+                destination
+            }),
+            Action::Rename => match change.final_destination() {
+                Some(dst) => {
                     info!(
                         "Renaming {} to {}",
-                        song.song_folder_path.as_path().display(),
-                        dst.as_path().display()
+                        source.as_path().display(),
+                        dst.display()
                     );
-                    std::fs::rename(song.song_folder_path.as_path(), dst)
-                } else {
-                    warn!("Path {} invalid", song.song_folder_path.as_path().display());
-                    continue;
+                    std::fs::rename(source.as_path(), dst)
+                        .map(|_| dst.to_path_buf())
+                        .map_err(Into::into)
                 }
+                None => Err("path has no parent".into()),
+            },
+        };
+        let (destination, outcome) = match &result {
+            Ok(destination) => (destination.clone(), Outcome::Succeeded),
+            Err(error) => {
+                warn!(
+                    "Failed to {} {}.{}",
+                    action.as_str(),
+                    source.as_path().display(),
+                    error
+                );
+                (PathBuf::new(), Outcome::Failed(error.to_string()))
             }
-        } {
-            warn!(
-                "Failed to {} {}.{}",
-                action.as_str(),
-                song.song_folder_path.as_path().display(),
-                error
-            );
-        }
+        };
+        batch.operations.push(JournalOperation {
+            action: action.clone(),
+            source,
+            destination,
+            trash_id,
+            timestamp: unix_time(),
+            outcome,
+            undo: UndoState::Pending,
+        });
+        results.push(ChangeResult {
+            song: song.clone(),
+            action: action.clone(),
+            outcome: result.map_err(|error| ChangeError::new(error.as_ref())),
+        });
+    }
+    if let Err(error) = Journal::new(song_folder).append(batch) {
+        warn!("Save journal failed. {}", error);
     }
+    results
 }
@@ -0,0 +1,191 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    apply_changes, default_parallelism,
+    error::{Error, Result},
+    naming::NamingTemplate,
+    plan::{ChangePlan, Resolution},
+    scan_songs, Action, InvalidReason, Snapshot, Song, DEFAULT_ID,
+};
+
+/// How [`Library::scan_with`] loads a song folder.
+#[derive(Clone, Debug)]
+pub struct ScanOptions {
+    /// At most this many song folders are loaded at the same time, one per CPU core by default.
+    pub parallelism: usize,
+    /// Parse and hash every song folder again instead of reusing the library index.
+    pub full_rescan: bool,
+    /// Write the library index to `.bs_song_manager/index.json` in the song folder after the
+    /// scan, so that the next one is faster. On by default.
+    pub update_index: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            parallelism: default_parallelism(),
+            full_rescan: false,
+            update_index: true,
+        }
+    }
+}
+
+/// Songs loaded from a song folder, usually `Beat Saber_Data/CustomLevels`.
+pub struct Library {
+    song_folder: PathBuf,
+    songs: Vec<Song>,
+    invalid: Vec<(PathBuf, InvalidReason)>,
+    lookup_error: Option<Error>,
+}
+
+impl Library {
+    /// Load every song folder in `song_folder` and look up the BeatSaver ids of their level
+    /// hashes, using one thread per CPU core.
+    ///
+    /// Folders whose files haven't changed since the last scan are read from the library index
+    /// kept in the song folder instead of being parsed and hashed again. The index is updated
+    /// afterwards, use [`Library::scan_with`] to leave the song folder untouched.
+    pub fn scan(song_folder: impl AsRef<Path>) -> Result<Self> {
+        Library::scan_with(song_folder, &ScanOptions::default())
+    }
+
+    /// Like [`Library::scan`] with at most `parallelism` song folders loaded at the same time.
+    pub fn scan_with_parallelism(
+        song_folder: impl AsRef<Path>,
+        parallelism: usize,
+    ) -> Result<Self> {
+        let options = ScanOptions {
+            parallelism,
+            ..ScanOptions::default()
+        };
+        Library::scan_with(song_folder, &options)
+    }
+
+    /// Like [`Library::scan`], loading the song folder as `options` say.
+    pub fn scan_with(song_folder: impl AsRef<Path>, options: &ScanOptions) -> Result<Self> {
+        let song_folder = song_folder.as_ref();
+        std::fs::read_dir(song_folder).map_err(|error| Error::io(song_folder, error))?;
+        let result = scan_songs(song_folder, options);
+        Ok(Library {
+            song_folder: song_folder.to_path_buf(),
+            songs: result.songs,
+            invalid: result.invalid,
+            lookup_error: result.lookup_error,
+        })
+    }
+
+    pub fn song_folder(&self) -> &Path {
+        &self.song_folder
+    }
+
+    /// Loaded songs, ordered by folder path.
+    pub fn songs(&self) -> &[Song] {
+        &self.songs
+    }
+
+    /// Entries of the song folder that couldn't be loaded as a song.
+    pub fn invalid(&self) -> &[(PathBuf, InvalidReason)] {
+        &self.invalid
+    }
+
+    /// Why looking up level ids on BeatSaver failed, the songs of [`Library::unresolved`] keep
+    /// an unknown level id then. The scan itself still succeeds.
+    pub fn lookup_error(&self) -> Option<&Error> {
+        self.lookup_error.as_ref()
+    }
+
+    /// Songs whose BeatSaver level id is unknown.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Song> {
+        self.songs.iter().filter(|song| song.level_id == DEFAULT_ID)
+    }
+
+    pub fn find_by_hash(&self, level_hash: &str) -> Option<&Song> {
+        self.songs
+            .iter()
+            .find(|song| song.level_hash.eq_ignore_ascii_case(level_hash))
+    }
+
+    /// Capture the loaded songs, to save as JSON or compare with a later scan.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(&self.song_folder, &self.songs)
+    }
+}
+
+/// Renames and deletes to apply to the songs of a library together.
+///
+/// Deleted songs are moved to the trash of the song folder and every applied set is recorded
+/// in its journal, the same as committing in the app.
+#[derive(Default)]
+pub struct ChangeSet {
+    changes: HashMap<Song, Action>,
+    naming: NamingTemplate,
+}
+
+impl ChangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `template` for renames instead of `"{id} ({name} - {mapper})"`.
+    ///
+    /// Tokens are `{id}`, `{hash}`, `{name}`, `{sub_name}`, `{song_author}`, `{mapper}` and
+    /// `{bpm}`. Text is transliterated to ASCII unless `keep_unicode` is set.
+    pub fn with_template(mut self, template: &str, keep_unicode: bool) -> Self {
+        self.naming = NamingTemplate {
+            template: template.to_string(),
+            keep_unicode,
+        };
+        self
+    }
+
+    pub fn delete(&mut self, song: &Song) {
+        self.changes.insert(song.clone(), Action::Delete);
+    }
+
+    pub fn rename(&mut self, song: &Song) {
+        self.changes.insert(song.clone(), Action::Rename);
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Apply the changes to the songs of `song_folder`.
+    ///
+    /// Returns the folder of every changed song paired with where it ended up, or why it was
+    /// left alone. Renames that would clash with another folder are skipped.
+    pub fn apply(&self, song_folder: impl AsRef<Path>) -> Vec<(PathBuf, Result<PathBuf>)> {
+        let plan = ChangePlan::new(&self.changes, &self.naming);
+        let mut results: Vec<(PathBuf, Result<PathBuf>)> = plan
+            .changes
+            .iter()
+            .filter(|change| change.resolution == Resolution::Skip)
+            .map(|change| {
+                let path = change.song.song_folder_path.clone();
+                let reason = change.conflict.map(|conflict| conflict.as_str());
+                (
+                    path.clone(),
+                    Err(Error::Conflict {
+                        path,
+                        reason: reason.unwrap_or("skipped"),
+                    }),
+                )
+            })
+            .collect();
+        for result in apply_changes(song_folder.as_ref(), &plan) {
+            let path = result.song.song_folder_path;
+            let outcome = result.outcome.map_err(|error| {
+                Error::io(path.clone(), std::io::Error::new(error.kind, error.message))
+            });
+            results.push((path, outcome));
+        }
+        results
+    }
+}
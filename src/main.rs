@@ -1,8 +1,34 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+use clap::Parser;
 use log::{info, warn};
+use std::process::ExitCode;
+
+/// Release builds on Windows have no console of their own, write the output of headless
+/// commands to the console they were started from instead.
+#[cfg(all(windows, not(debug_assertions)))]
+fn attach_parent_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    // SAFETY: AttachConsole has no preconditions, it fails when the parent has no console, e.g.
+    // when started from the file manager.
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+fn main() -> ExitCode {
+    // Before parsing, so that help and usage errors show up too.
+    #[cfg(all(windows, not(debug_assertions)))]
+    attach_parent_console();
+    let cli = bs_song_manager::cli::Cli::parse();
+    if let Some(command) = cli.command {
+        // Keep stdout for the output of the command.
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .init();
+        return bs_song_manager::cli::run(command);
+    }
 
-fn main() {
     // Log to stdout (if you run with `RUST_LOG=debug`).
     tracing_subscriber::fmt::init();
 
@@ -20,4 +46,5 @@ fn main() {
         native_options,
         Box::new(|cc| Box::new(bs_song_manager::ManagerApp::new(cc))),
     );
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,174 @@
+use deunicode::deunicode;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::Song;
+
+/// Named templates to pick from, the first one is the default.
+pub(crate) const PRESETS: &[(&str, &str)] = &[
+    ("BeatSaver", "{id} ({name} - {mapper})"),
+    ("Song Author", "{id} ({song_author} - {name})"),
+    ("Name", "{name} - {mapper}"),
+    ("ID", "{id}"),
+    ("Hash", "{hash}"),
+];
+
+/// Tokens a template can contain, for the hint next to the template editor.
+pub(crate) const TOKENS: &[&str] = &[
+    "{id}",
+    "{hash}",
+    "{name}",
+    "{sub_name}",
+    "{song_author}",
+    "{mapper}",
+    "{bpm}",
+];
+
+static TOKEN_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{(\w+)\}").unwrap());
+/// Characters that aren't allowed in file names on Windows, plus the token braces.
+static ILLEGAL_CHARACTERS_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"[~#"%&*:<>?/\\{|}]+"#).unwrap());
+
+/// How song folders are named by Rename.
+///
+/// The BeatSaver preset without Unicode is also the naming method of the song package shared
+/// by WGzeyu(https://bs.wgzeyu.com/).
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct NamingTemplate {
+    pub(crate) template: String,
+    /// Keep the original text instead of transliterating it to ASCII.
+    pub(crate) keep_unicode: bool,
+}
+
+impl Default for NamingTemplate {
+    fn default() -> Self {
+        NamingTemplate {
+            template: PRESETS[0].1.to_string(),
+            keep_unicode: false,
+        }
+    }
+}
+
+impl NamingTemplate {
+    /// Folder name of `song`, with the characters that aren't allowed in file names replaced.
+    pub(crate) fn render(&self, song: &Song) -> String {
+        let text = |text: &str| {
+            if self.keep_unicode {
+                text.to_string()
+            } else {
+                deunicode(text)
+            }
+        };
+        // One pass, so that a value containing a token isn't expanded again.
+        let name =
+            TOKEN_REGEX.replace_all(&self.template, |captures: &Captures| match &captures[1] {
+                "id" => song.level_id.clone(),
+                "hash" => song.level_hash.clone(),
+                "name" => text(&song.song_name),
+                "sub_name" => text(&song.song_sub_name),
+                "song_author" => text(&song.song_author_name),
+                "mapper" => text(&song.level_author_name),
+                "bpm" => song.beats_per_minute.to_string(),
+                _ => captures[0].to_string(),
+            });
+        ILLEGAL_CHARACTERS_REGEX
+            .replace_all(name.trim(), "_")
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn song() -> Song {
+        let mut song = Song::placeholder(Path::new("song"));
+        song.level_id = "1a2b".to_string();
+        song.level_hash = "abcdef".to_string();
+        song.song_name = "Song".to_string();
+        song.song_sub_name = "Sub".to_string();
+        song.song_author_name = "Author".to_string();
+        song.level_author_name = "Mapper".to_string();
+        song.beats_per_minute = 128.0;
+        song
+    }
+
+    fn template(template: &str, keep_unicode: bool) -> NamingTemplate {
+        NamingTemplate {
+            template: template.to_string(),
+            keep_unicode,
+        }
+    }
+
+    #[test]
+    fn substitutes_every_token() {
+        let naming = template(
+            "{id} {hash} {name} {sub_name} {song_author} {mapper} {bpm}",
+            false,
+        );
+        assert_eq!(
+            naming.render(&song()),
+            "1a2b abcdef Song Sub Author Mapper 128"
+        );
+    }
+
+    #[test]
+    fn default_is_beatsaver_preset() {
+        assert_eq!(
+            NamingTemplate::default().render(&song()),
+            "1a2b (Song - Mapper)"
+        );
+    }
+
+    #[test]
+    fn presets_render_without_leftover_tokens() {
+        for (_, preset) in PRESETS {
+            let name = template(preset, false).render(&song());
+            assert!(!name.contains('_'), "{} rendered as {}", preset, name);
+        }
+    }
+
+    #[test]
+    fn values_are_not_expanded_again() {
+        let mut song = song();
+        song.song_name = "{hash}".to_string();
+        song.level_author_name = "{id}".to_string();
+        let naming = template("{id} ({name} - {mapper})", false);
+        assert_eq!(naming.render(&song), "1a2b (_hash_ - _id_)");
+    }
+
+    #[test]
+    fn unknown_tokens_are_kept_as_text() {
+        assert_eq!(
+            template("{id} {unknown}", false).render(&song()),
+            "1a2b _unknown_"
+        );
+    }
+
+    #[test]
+    fn transliterates_unless_unicode_is_kept() {
+        let mut song = song();
+        song.song_name = "Café".to_string();
+        song.level_author_name = "Björk".to_string();
+        assert_eq!(
+            template("{name} - {mapper}", false).render(&song),
+            "Cafe - Bjork"
+        );
+        assert_eq!(
+            template("{name} - {mapper}", true).render(&song),
+            "Café - Björk"
+        );
+    }
+
+    #[test]
+    fn replaces_illegal_characters() {
+        let mut song = song();
+        song.song_name = r#"a<b>c:"d"/e\f|g?h*"#.to_string();
+        assert_eq!(template("{name}", false).render(&song), "a_b_c_d_e_f_g_h_");
+        song.song_name = "  padded  ".to_string();
+        assert_eq!(template("{name}", false).render(&song), "padded");
+    }
+}
@@ -0,0 +1,467 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{naming::NamingTemplate, Action, Song};
+
+/// Longest folder name most file systems accept, in bytes.
+const MAX_NAME_LEN: usize = 255;
+/// Longest path the Windows API accepts without long path support.
+const MAX_PATH_LEN: usize = 260;
+/// Names Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Conflict {
+    /// The folder already has the new name.
+    AlreadyCanonical,
+    /// Another folder with the new name exists, and isn't renamed away first.
+    DestinationExists,
+    /// Another pending rename uses the same new name.
+    DuplicateDestination,
+    NameTooLong,
+    InvalidName,
+}
+
+impl Conflict {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Conflict::AlreadyCanonical => "already named canonically",
+            Conflict::DestinationExists => "destination exists",
+            Conflict::DuplicateDestination => "same name as another rename",
+            Conflict::NameTooLong => "name too long",
+            Conflict::InvalidName => "invalid name",
+        }
+    }
+
+    /// Whether a numbered suffix resolves the conflict.
+    pub(crate) fn can_suffix(&self) -> bool {
+        matches!(
+            self,
+            Conflict::DestinationExists | Conflict::DuplicateDestination
+        )
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) enum Resolution {
+    Apply,
+    /// Rename to this destination instead, which has a numbered suffix.
+    Suffix(PathBuf),
+    Skip,
+}
+
+/// A pending change as it would be applied.
+pub(crate) struct PlannedChange {
+    pub(crate) song: Song,
+    pub(crate) action: Action,
+    /// New folder of a rename.
+    pub(crate) destination: Option<PathBuf>,
+    pub(crate) conflict: Option<Conflict>,
+    pub(crate) resolution: Resolution,
+}
+
+impl PlannedChange {
+    /// Folder the song ends up in after a rename, taking a suffix into account.
+    pub(crate) fn final_destination(&self) -> Option<&Path> {
+        match &self.resolution {
+            Resolution::Suffix(destination) => Some(destination),
+            _ => self.destination.as_deref(),
+        }
+    }
+}
+
+/// Dry run of the pending changes, checked for conflicts before anything touches the disk.
+pub(crate) struct ChangePlan {
+    pub(crate) changes: Vec<PlannedChange>,
+}
+
+fn invalid_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default();
+    name.trim().is_empty()
+        || name.ends_with('.')
+        || name.ends_with(' ')
+        || name.chars().any(|c| c.is_control())
+        || RESERVED_NAMES
+            .iter()
+            .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+/// Whether `a` and `b` are the same folder, e.g. differing only in case on a case-insensitive
+/// file system.
+fn same_folder(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Mark renames onto the folder of another rename as `DestinationExists` where that folder
+/// doesn't get renamed away: because the other rename conflicts, or because the renames form a
+/// cycle, like two folders swapping names.
+fn block_renames_onto_kept_folders(changes: &[PlannedChange], conflicts: &mut [Option<Conflict>]) {
+    let renamed_from: HashMap<&Path, usize> = changes
+        .iter()
+        .enumerate()
+        .filter(|(_, change)| change.action == Action::Rename)
+        .map(|(index, change)| (change.song.song_folder_path.as_path(), index))
+        .collect();
+    // The rename that has to move out of the destination of each rename first.
+    let next: Vec<Option<usize>> = changes
+        .iter()
+        .enumerate()
+        .map(|(index, change)| {
+            let destination = change.destination.as_deref()?;
+            renamed_from
+                .get(destination)
+                .copied()
+                .filter(|&other| other != index)
+        })
+        .collect();
+    for (start, conflict) in conflicts.iter_mut().enumerate() {
+        let mut index = start;
+        for _ in 0..changes.len() {
+            match next[index] {
+                Some(following) if following == start => {
+                    *conflict = Some(Conflict::DestinationExists);
+                    break;
+                }
+                Some(following) => index = following,
+                None => break,
+            }
+        }
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..changes.len() {
+            if let (None, Some(following)) = (conflicts[index], next[index]) {
+                if conflicts[following].is_some() {
+                    conflicts[index] = Some(Conflict::DestinationExists);
+                    changed = true;
+                }
+            }
+        }
+    }
+}
+
+impl ChangePlan {
+    pub(crate) fn new(pending_changes: &HashMap<Song, Action>, naming: &NamingTemplate) -> Self {
+        let mut changes: Vec<PlannedChange> = pending_changes
+            .iter()
+            .map(|(song, action)| PlannedChange {
+                song: song.clone(),
+                action: action.clone(),
+                destination: match action {
+                    Action::Delete => None,
+                    Action::Rename => song
+                        .song_folder_path
+                        .parent()
+                        .map(|parent| parent.join(song.get_canonical_name(naming))),
+                },
+                conflict: None,
+                resolution: Resolution::Apply,
+            })
+            .collect();
+        changes.sort_by(|a, b| a.song.song_folder_path.cmp(&b.song.song_folder_path));
+
+        // Deletes are applied first and renames are ordered so that a folder is renamed away
+        // before another one takes its name, so the sources of both are free to be renamed to.
+        let vacated: HashSet<&Path> = changes
+            .iter()
+            .map(|change| change.song.song_folder_path.as_path())
+            .collect();
+        let mut conflicts = Vec::new();
+        let mut destinations = HashSet::new();
+        for change in &changes {
+            let source = change.song.song_folder_path.as_path();
+            conflicts.push(match &change.destination {
+                None if change.action == Action::Rename => Some(Conflict::InvalidName),
+                None => None,
+                Some(destination) => {
+                    let name = destination
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    if destination == source {
+                        Some(Conflict::AlreadyCanonical)
+                    } else if invalid_name(&name) {
+                        Some(Conflict::InvalidName)
+                    } else if name.len() > MAX_NAME_LEN
+                        || (cfg!(windows) && destination.as_os_str().len() >= MAX_PATH_LEN)
+                    {
+                        Some(Conflict::NameTooLong)
+                    } else if !destinations.insert(destination.clone()) {
+                        Some(Conflict::DuplicateDestination)
+                    } else if destination.exists()
+                        && !vacated.contains(destination.as_path())
+                        && !same_folder(destination, source)
+                    {
+                        Some(Conflict::DestinationExists)
+                    } else {
+                        None
+                    }
+                }
+            });
+        }
+        block_renames_onto_kept_folders(&changes, &mut conflicts);
+        for (change, conflict) in changes.iter_mut().zip(conflicts) {
+            change.conflict = conflict;
+            if conflict.is_some() {
+                change.resolution = Resolution::Skip;
+            }
+        }
+        ChangePlan { changes }
+    }
+
+    /// Resolve the conflict of the change at `index` by renaming to the first free
+    /// `"{name} (n)"`.
+    pub(crate) fn suffix(&mut self, index: usize) {
+        let destination = match &self.changes[index].destination {
+            Some(destination) => destination.clone(),
+            None => return,
+        };
+        let taken: HashSet<PathBuf> = self
+            .changes
+            .iter()
+            .enumerate()
+            .filter(|(other, change)| *other != index && change.resolution != Resolution::Skip)
+            .filter_map(|(_, change)| change.final_destination().map(Path::to_path_buf))
+            .collect();
+        let name = destination
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut number = 2;
+        let suffixed = loop {
+            let suffixed = destination.with_file_name(format!("{} ({})", name, number));
+            if !suffixed.exists() && !taken.contains(&suffixed) {
+                break suffixed;
+            }
+            number += 1;
+        };
+        self.changes[index].resolution = Resolution::Suffix(suffixed);
+    }
+
+    /// Changes that will be applied, deletes before renames, and a rename before the one taking
+    /// over its folder.
+    pub(crate) fn applied(&self) -> impl Iterator<Item = &PlannedChange> {
+        let deletes = self
+            .changes
+            .iter()
+            .filter(|change| change.action == Action::Delete);
+        let mut renames: Vec<&PlannedChange> = self
+            .changes
+            .iter()
+            .filter(|change| change.action == Action::Rename)
+            .filter(|change| change.resolution != Resolution::Skip)
+            .collect();
+        let mut ordered = Vec::with_capacity(renames.len());
+        while !renames.is_empty() {
+            let sources: HashSet<&Path> = renames
+                .iter()
+                .map(|change| change.song.song_folder_path.as_path())
+                .collect();
+            let (ready, waiting): (Vec<&PlannedChange>, Vec<&PlannedChange>) =
+                renames.iter().partition(|change| {
+                    change
+                        .final_destination()
+                        .map_or(true, |destination| !sources.contains(destination))
+                });
+            if ready.is_empty() {
+                // Cycles conflict in `new`, this only keeps a changed plan from looping forever.
+                ordered.extend(waiting);
+                break;
+            }
+            ordered.extend(ready);
+            renames = waiting;
+        }
+        deletes
+            .filter(|change| change.resolution != Resolution::Skip)
+            .chain(ordered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{song, TempFolder};
+
+    fn plan(changes: &[(&Song, Action)]) -> ChangePlan {
+        plan_with(changes, &NamingTemplate::default())
+    }
+
+    fn plan_with(changes: &[(&Song, Action)], naming: &NamingTemplate) -> ChangePlan {
+        let pending_changes: HashMap<Song, Action> = changes
+            .iter()
+            .map(|(song, action)| ((*song).clone(), action.clone()))
+            .collect();
+        ChangePlan::new(&pending_changes, naming)
+    }
+
+    fn change<'a>(plan: &'a ChangePlan, song: &Song) -> &'a PlannedChange {
+        plan.changes
+            .iter()
+            .find(|change| change.song.song_folder_path == song.song_folder_path)
+            .unwrap()
+    }
+
+    #[test]
+    fn renames_without_conflict() {
+        let song_folder = TempFolder::new("plain");
+        let song = song(song_folder.join("old"), "", "1a");
+        let plan = plan(&[(&song, Action::Rename)]);
+        let change = change(&plan, &song);
+        assert!(change.conflict.is_none());
+        assert!(change.resolution == Resolution::Apply);
+        assert_eq!(
+            change.final_destination(),
+            Some(song_folder.join("1a (Song - Mapper)").as_path())
+        );
+    }
+
+    #[test]
+    fn skips_canonically_named_folder() {
+        let song_folder = TempFolder::new("canonical");
+        let song = song(song_folder.join("1a (Song - Mapper)"), "", "1a");
+        let plan = plan(&[(&song, Action::Rename)]);
+        let change = change(&plan, &song);
+        assert!(change.conflict == Some(Conflict::AlreadyCanonical));
+        assert!(change.resolution == Resolution::Skip);
+        assert!(!Conflict::AlreadyCanonical.can_suffix());
+        assert_eq!(plan.applied().count(), 0);
+    }
+
+    #[test]
+    fn existing_destination_conflicts_unless_deleted() {
+        let song_folder = TempFolder::new("exists");
+        std::fs::create_dir(song_folder.join("1a (Song - Mapper)")).unwrap();
+        let renamed = song(song_folder.join("old"), "", "1a");
+        let plan_rename = plan(&[(&renamed, Action::Rename)]);
+        assert!(change(&plan_rename, &renamed).conflict == Some(Conflict::DestinationExists));
+
+        let deleted = song(song_folder.join("1a (Song - Mapper)"), "", "1a");
+        let plan = plan(&[(&renamed, Action::Rename), (&deleted, Action::Delete)]);
+        assert!(change(&plan, &renamed).conflict.is_none());
+        let order: Vec<&Action> = plan.applied().map(|change| &change.action).collect();
+        assert!(order == [&Action::Delete, &Action::Rename]);
+    }
+
+    #[test]
+    fn chained_renames_run_in_order() {
+        let song_folder = TempFolder::new("chain");
+        std::fs::create_dir(song_folder.join("1a (Song - Mapper)")).unwrap();
+        let first = song(song_folder.join("old"), "", "1a");
+        let second = song(song_folder.join("1a (Song - Mapper)"), "", "2b");
+        let plan = plan(&[(&first, Action::Rename), (&second, Action::Rename)]);
+        assert!(change(&plan, &first).conflict.is_none());
+        assert!(change(&plan, &second).conflict.is_none());
+        let order: Vec<&Path> = plan
+            .applied()
+            .map(|change| change.song.song_folder_path.as_path())
+            .collect();
+        assert_eq!(order, [second.song_folder_path, first.song_folder_path]);
+    }
+
+    #[test]
+    fn rename_onto_a_folder_that_stays_conflicts() {
+        let song_folder = TempFolder::new("blocked");
+        std::fs::create_dir(song_folder.join("1a (Song - Mapper)")).unwrap();
+        std::fs::create_dir(song_folder.join("2b (Song - Mapper)")).unwrap();
+        let first = song(song_folder.join("old"), "", "1a");
+        let second = song(song_folder.join("1a (Song - Mapper)"), "", "2b");
+        let plan = plan(&[(&first, Action::Rename), (&second, Action::Rename)]);
+        assert!(change(&plan, &second).conflict == Some(Conflict::DestinationExists));
+        assert!(change(&plan, &first).conflict == Some(Conflict::DestinationExists));
+        assert_eq!(plan.applied().count(), 0);
+    }
+
+    #[test]
+    fn swapped_names_conflict() {
+        let song_folder = TempFolder::new("swap");
+        std::fs::create_dir(song_folder.join("1a (Song - Mapper)")).unwrap();
+        std::fs::create_dir(song_folder.join("2b (Song - Mapper)")).unwrap();
+        let first = song(song_folder.join("2b (Song - Mapper)"), "", "1a");
+        let second = song(song_folder.join("1a (Song - Mapper)"), "", "2b");
+        let plan = plan(&[(&first, Action::Rename), (&second, Action::Rename)]);
+        assert!(change(&plan, &first).conflict == Some(Conflict::DestinationExists));
+        assert!(change(&plan, &second).conflict == Some(Conflict::DestinationExists));
+    }
+
+    #[test]
+    fn second_rename_to_the_same_name_conflicts() {
+        let song_folder = TempFolder::new("duplicate");
+        let first = song(song_folder.join("a"), "", "1a");
+        let second = song(song_folder.join("b"), "", "1a");
+        let plan = plan(&[(&first, Action::Rename), (&second, Action::Rename)]);
+        assert!(change(&plan, &first).conflict.is_none());
+        assert!(change(&plan, &second).conflict == Some(Conflict::DuplicateDestination));
+        assert!(change(&plan, &second).resolution == Resolution::Skip);
+    }
+
+    #[test]
+    fn invalid_and_long_names_conflict() {
+        let song_folder = TempFolder::new("invalid");
+        let naming = NamingTemplate {
+            template: "{name}".to_string(),
+            ..NamingTemplate::default()
+        };
+        let mut reserved = song(song_folder.join("a"), "", "1a");
+        reserved.song_name = "con".to_string();
+        let mut long = song(song_folder.join("b"), "", "2b");
+        long.song_name = "x".repeat(MAX_NAME_LEN + 1);
+        let plan = plan_with(
+            &[(&reserved, Action::Rename), (&long, Action::Rename)],
+            &naming,
+        );
+        assert!(change(&plan, &reserved).conflict == Some(Conflict::InvalidName));
+        assert!(change(&plan, &long).conflict == Some(Conflict::NameTooLong));
+        assert!(!Conflict::InvalidName.can_suffix() && !Conflict::NameTooLong.can_suffix());
+    }
+
+    #[test]
+    fn suffix_takes_first_free_number() {
+        let song_folder = TempFolder::new("suffix");
+        std::fs::create_dir(song_folder.join("1a (Song - Mapper)")).unwrap();
+        std::fs::create_dir(song_folder.join("1a (Song - Mapper) (2)")).unwrap();
+        let song = song(song_folder.join("old"), "", "1a");
+        let mut plan = plan(&[(&song, Action::Rename)]);
+        assert!(Conflict::DestinationExists.can_suffix());
+        plan.suffix(0);
+        assert_eq!(
+            plan.changes[0].final_destination(),
+            Some(song_folder.join("1a (Song - Mapper) (3)").as_path())
+        );
+        assert_eq!(plan.applied().count(), 1);
+    }
+
+    #[test]
+    fn suffixes_of_duplicate_renames_differ() {
+        let song_folder = TempFolder::new("suffixes");
+        let songs: Vec<Song> = ["a", "b", "c"]
+            .iter()
+            .map(|folder| song(song_folder.join(folder), "", "1a"))
+            .collect();
+        let changes: Vec<(&Song, Action)> =
+            songs.iter().map(|song| (song, Action::Rename)).collect();
+        let mut plan = plan(&changes);
+        plan.suffix(1);
+        plan.suffix(2);
+        let destinations: Vec<PathBuf> = plan
+            .applied()
+            .filter_map(|change| change.final_destination().map(Path::to_path_buf))
+            .collect();
+        assert_eq!(
+            destinations,
+            [
+                song_folder.join("1a (Song - Mapper)"),
+                song_folder.join("1a (Song - Mapper) (2)"),
+                song_folder.join("1a (Song - Mapper) (3)"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{Error, Result, Song, DEFAULT_ID};
+
+/// Playlist file used by the PlaylistManager mod, usually saved with `.bplist` extension.
+///
+/// Refer https://github.com/rithik-b/PlaylistManager
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Playlist {
+    playlist_title: String,
+    playlist_author: String,
+    #[serde(default)]
+    playlist_description: String,
+    /// Cover image encoded as base64, optionally prefixed with a `data:` uri header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+    songs: Vec<PlaylistSong>,
+}
+
+/// One entry of a [`Playlist`].
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistSong {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(default)]
+    hash: String,
+    #[serde(default)]
+    song_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    level_author_name: Option<String>,
+    #[serde(default, rename = "levelid", skip_serializing_if = "Option::is_none")]
+    level_id: Option<String>,
+}
+
+impl PlaylistSong {
+    /// Level hash of the entry, taken from `levelid` for entries that don't carry one. Empty
+    /// if the entry only has a BeatSaver key.
+    pub fn hash(&self) -> &str {
+        if !self.hash.is_empty() {
+            return &self.hash;
+        }
+        self.level_id
+            .as_deref()
+            .map(|level_id| level_id.trim_start_matches("custom_level_"))
+            .unwrap_or_default()
+    }
+
+    /// BeatSaver key, like `1a2b`.
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    pub fn song_name(&self) -> &str {
+        &self.song_name
+    }
+
+    pub fn level_author_name(&self) -> Option<&str> {
+        self.level_author_name.as_deref()
+    }
+
+    fn from_song(song: &Song) -> Self {
+        PlaylistSong {
+            key: if song.level_id == DEFAULT_ID {
+                None
+            } else {
+                Some(song.level_id.clone())
+            },
+            hash: song.level_hash.to_uppercase(),
+            song_name: song.song_name.clone(),
+            level_author_name: Some(song.level_author_name.clone()),
+            level_id: Some(format!("custom_level_{}", song.level_hash.to_uppercase())),
+        }
+    }
+}
+
+/// A playlist entry paired with the local song it refers to, if there is one.
+pub struct PlaylistMatch {
+    pub(crate) song_name: String,
+    /// Lowercase level hash of the entry, see [`PlaylistSong::hash`].
+    pub(crate) hash: String,
+    pub(crate) song: Option<Song>,
+}
+
+impl PlaylistMatch {
+    /// Song name as written in the playlist.
+    pub fn song_name(&self) -> &str {
+        &self.song_name
+    }
+
+    /// Lowercase level hash of the entry, empty if it only has a BeatSaver key.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// The song of the library the entry refers to, `None` if it isn't downloaded.
+    pub fn song(&self) -> Option<&Song> {
+        self.song.as_ref()
+    }
+}
+
+fn image_mime_type(data: &[u8]) -> &'static str {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else {
+        "image/png"
+    }
+}
+
+impl Playlist {
+    /// Build a playlist from songs, the cover is taken from `cover` if it can be read.
+    pub fn new(
+        title: &str,
+        author: &str,
+        description: &str,
+        songs: &[Song],
+        cover: Option<&Song>,
+    ) -> Self {
+        let image = cover.and_then(|song| song.read_cover_image()).map(|data| {
+            format!(
+                "data:{};base64,{}",
+                image_mime_type(&data),
+                BASE64.encode(&data)
+            )
+        });
+        Playlist {
+            playlist_title: title.to_string(),
+            playlist_author: author.to_string(),
+            playlist_description: description.to_string(),
+            image,
+            songs: songs.iter().map(PlaylistSong::from_song).collect(),
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        info!("Loading playlist from {}", path.display());
+        let file = File::open(path).map_err(|error| Error::io(path, error))?;
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|error| Error::from_json(path, &error))
+    }
+
+    pub fn title(&self) -> &str {
+        &self.playlist_title
+    }
+
+    pub fn author(&self) -> &str {
+        &self.playlist_author
+    }
+
+    pub fn description(&self) -> &str {
+        &self.playlist_description
+    }
+
+    /// Entries in playlist order.
+    pub fn songs(&self) -> &[PlaylistSong] {
+        &self.songs
+    }
+
+    /// Match every entry against `song_list`, e.g. [`Library::songs`](crate::Library::songs), by
+    /// level hash, falling back to the hash in `levelid` and then the BeatSaver key for entries
+    /// that don't carry one. The matches are in playlist order.
+    pub fn match_songs(&self, song_list: &[Song]) -> Vec<PlaylistMatch> {
+        let mut hash_map = HashMap::new();
+        let mut id_map = HashMap::new();
+        for song in song_list {
+            hash_map.insert(song.level_hash.to_lowercase(), song);
+            if song.level_id != DEFAULT_ID {
+                id_map.insert(song.level_id.to_lowercase(), song);
+            }
+        }
+        self.songs
+            .iter()
+            .map(|entry| {
+                let hash = entry.hash().to_lowercase();
+                let song = match hash_map.get(&hash) {
+                    Some(song) => Some(*song),
+                    None if hash.is_empty() => entry
+                        .key
+                        .as_ref()
+                        .and_then(|key| id_map.get(&key.to_lowercase()).copied()),
+                    None => None,
+                };
+                PlaylistMatch {
+                    song_name: entry.song_name.clone(),
+                    hash,
+                    song: song.cloned(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        info!(
+            "Saving playlist {} with {} songs to {}",
+            self.playlist_title,
+            self.songs.len(),
+            path.display()
+        );
+        let file = File::create(path).map_err(|error| Error::io(path, error))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self).map_err(|error| {
+            if error.is_io() {
+                Error::io(path, error.into())
+            } else {
+                Error::from_json(path, &error)
+            }
+        })?;
+        // Dropping the writer would flush it too, but swallow the error.
+        writer.flush().map_err(|error| Error::io(path, error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{song, TempFolder};
+    use std::path::PathBuf;
+
+    fn entry(hash: &str, key: Option<&str>, level_id: Option<&str>) -> PlaylistSong {
+        PlaylistSong {
+            key: key.map(str::to_string),
+            hash: hash.to_string(),
+            song_name: String::new(),
+            level_author_name: None,
+            level_id: level_id.map(str::to_string),
+        }
+    }
+
+    fn matched_folders(playlist: &Playlist, song_list: &[Song]) -> Vec<Option<PathBuf>> {
+        playlist
+            .match_songs(song_list)
+            .into_iter()
+            .map(|entry| entry.song.map(|song| song.song_folder_path))
+            .collect()
+    }
+
+    #[test]
+    fn matches_by_hash_then_level_id_then_key() {
+        let song_list = [
+            song("by hash", "abc123", DEFAULT_ID),
+            song("by level id", "def456", DEFAULT_ID),
+            song("by key", "0f0f0f", "1a2b"),
+        ];
+        let playlist = Playlist {
+            songs: vec![
+                entry("ABC123", None, None),
+                entry("", None, Some("custom_level_DEF456")),
+                entry("", Some("1A2B"), None),
+                entry("ffffff", Some("1a2b"), None),
+            ],
+            ..Playlist::default()
+        };
+        assert_eq!(
+            matched_folders(&playlist, &song_list),
+            [
+                Some(PathBuf::from("by hash")),
+                Some(PathBuf::from("by level id")),
+                Some(PathBuf::from("by key")),
+                // A hash that isn't in the library doesn't fall back to the key.
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let songs = [
+            song("song", "abc123", "1a2b"),
+            song("other", "def456", DEFAULT_ID),
+        ];
+        let playlist = Playlist::new("Title", "Author", "", &songs, None);
+        let folder = TempFolder::new("playlist");
+        let path = folder.join("playlist.bplist");
+        playlist.save(&path).unwrap();
+        let loaded = Playlist::from_path(&path).unwrap();
+        assert_eq!(loaded.title(), "Title");
+        assert_eq!(loaded.author(), "Author");
+        assert_eq!(loaded.songs()[0].hash(), "ABC123");
+        assert_eq!(loaded.songs()[0].key(), Some("1a2b"));
+        assert_eq!(loaded.songs()[1].key(), None);
+        assert_eq!(
+            matched_folders(&loaded, &songs),
+            [Some(PathBuf::from("song")), Some(PathBuf::from("other"))]
+        );
+    }
+}
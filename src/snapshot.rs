@@ -0,0 +1,317 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::{Error, Result},
+    journal::unix_time,
+    Song,
+};
+
+/// Every song of a song folder at one point in time, saved as JSON.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    song_folder: PathBuf,
+    /// Unix time in seconds.
+    created_at: u64,
+    songs: Vec<Song>,
+}
+
+/// What changed between two snapshots, songs are matched by folder path and then by level hash.
+#[derive(Default)]
+pub struct SnapshotDiff<'a> {
+    /// Songs only in the newer snapshot.
+    pub added: Vec<&'a Song>,
+    /// Songs only in the older snapshot.
+    pub removed: Vec<&'a Song>,
+    /// The same level in a different folder, as `(older, newer)`.
+    pub moved: Vec<(&'a Song, &'a Song)>,
+    /// The same folder with a different level or other metadata, as `(older, newer)`, see
+    /// [`SnapshotDiff::changed_fields`].
+    pub changed: Vec<(&'a Song, &'a Song)>,
+}
+
+impl<'a> SnapshotDiff<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.moved.is_empty()
+            && self.changed.is_empty()
+    }
+
+    /// Names of the fields, as written in the snapshot, that differ between two songs. The folder
+    /// path is left out.
+    pub fn changed_fields(older: &Song, newer: &Song) -> Vec<String> {
+        let (Ok(Value::Object(older)), Ok(Value::Object(newer))) =
+            (serde_json::to_value(older), serde_json::to_value(newer))
+        else {
+            return Vec::new();
+        };
+        newer
+            .iter()
+            .filter(|(field, value)| *field != "songFolderPath" && older.get(*field) != Some(value))
+            .map(|(field, _)| field.clone())
+            .collect()
+    }
+}
+
+impl Snapshot {
+    pub fn new(song_folder: impl Into<PathBuf>, songs: &[Song]) -> Self {
+        Snapshot {
+            song_folder: song_folder.into(),
+            created_at: unix_time(),
+            songs: songs.to_vec(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        info!("Loading snapshot from {}", path.display());
+        let file = File::open(path).map_err(|error| Error::io(path, error))?;
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|error| Error::from_json(path, &error))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        info!(
+            "Saving snapshot of {} songs to {}",
+            self.songs.len(),
+            path.display()
+        );
+        let file = File::create(path).map_err(|error| Error::io(path, error))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self).map_err(|error| {
+            if error.is_io() {
+                Error::io(path, error.into())
+            } else {
+                Error::from_json(path, &error)
+            }
+        })?;
+        // Dropping the writer would flush it too, but swallow the error.
+        writer.flush().map_err(|error| Error::io(path, error))
+    }
+
+    pub fn song_folder(&self) -> &Path {
+        &self.song_folder
+    }
+
+    pub fn created_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.created_at)
+    }
+
+    pub fn songs(&self) -> &[Song] {
+        &self.songs
+    }
+
+    /// Compare with a `newer` snapshot of the same song folder.
+    pub fn diff<'a>(&'a self, newer: &'a Snapshot) -> SnapshotDiff<'a> {
+        let old_paths: HashMap<&Path, &Song> = self
+            .songs
+            .iter()
+            .map(|song| (song.song_folder_path.as_path(), song))
+            .collect();
+        let new_paths: HashSet<&Path> = newer
+            .songs
+            .iter()
+            .map(|song| song.song_folder_path.as_path())
+            .collect();
+        let mut diff = SnapshotDiff::default();
+        let mut added = Vec::new();
+        for song in &newer.songs {
+            match old_paths.get(song.song_folder_path.as_path()) {
+                Some(old) if !SnapshotDiff::changed_fields(old, song).is_empty() => {
+                    diff.changed.push((old, song))
+                }
+                Some(_) => {}
+                None => added.push(song),
+            }
+        }
+        let mut removed: HashMap<String, Vec<&Song>> = HashMap::new();
+        for song in &self.songs {
+            if !new_paths.contains(song.song_folder_path.as_path()) {
+                removed
+                    .entry(song.level_hash.to_lowercase())
+                    .or_default()
+                    .push(song);
+            }
+        }
+        for song in added {
+            let old = removed
+                .get_mut(&song.level_hash.to_lowercase())
+                .and_then(|songs| songs.pop());
+            match old {
+                Some(old) => diff.moved.push((old, song)),
+                None => diff.added.push(song),
+            }
+        }
+        diff.removed = removed.into_values().flatten().collect();
+        diff.removed
+            .sort_by(|a, b| a.song_folder_path.cmp(&b.song_folder_path));
+        diff
+    }
+}
+
+/// Serialize `Option<SystemTime>` as Unix time in seconds.
+pub(crate) mod unix_seconds {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    pub(crate) fn serialize<S: Serializer>(
+        time: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let seconds = time
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+        seconds.serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        let seconds = Option::<u64>::deserialize(deserializer)?;
+        Ok(seconds.map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds)))
+    }
+}
+
+/// Serialize `Option<Duration>` as fractional seconds.
+pub(crate) mod seconds {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        duration
+            .map(|duration| duration.as_secs_f64())
+            .serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        let seconds = Option::<f64>::deserialize(deserializer)?;
+        Ok(seconds
+            .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+            .map(Duration::from_secs_f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{song, TempFolder},
+        InvalidReason, DEFAULT_ID,
+    };
+
+    fn paths<'a>(songs: &[&'a Song]) -> Vec<&'a Path> {
+        songs
+            .iter()
+            .map(|song| song.song_folder_path.as_path())
+            .collect()
+    }
+
+    #[test]
+    fn classifies_songs() {
+        let mut renamed_id = song("same hash", "AAAA", DEFAULT_ID);
+        let older = Snapshot::new(
+            "songs",
+            &[
+                song("kept", "1111", DEFAULT_ID),
+                song("removed", "2222", DEFAULT_ID),
+                song("before move", "3333", DEFAULT_ID),
+                song("remapped", "4444", DEFAULT_ID),
+                renamed_id.clone(),
+            ],
+        );
+        renamed_id.level_id = "1a2b".to_string();
+        let newer = Snapshot::new(
+            "songs",
+            &[
+                song("kept", "1111", DEFAULT_ID),
+                song("after move", "3333", DEFAULT_ID),
+                song("remapped", "5555", DEFAULT_ID),
+                renamed_id,
+                song("added", "6666", DEFAULT_ID),
+            ],
+        );
+        let diff = older.diff(&newer);
+        assert_eq!(paths(&diff.added), [Path::new("added")]);
+        assert_eq!(paths(&diff.removed), [Path::new("removed")]);
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(diff.moved[0].0.song_folder_path, Path::new("before move"));
+        assert_eq!(diff.moved[0].1.song_folder_path, Path::new("after move"));
+        let changed: Vec<(&Path, Vec<String>)> = diff
+            .changed
+            .iter()
+            .map(|(old, new)| {
+                (
+                    new.song_folder_path.as_path(),
+                    SnapshotDiff::changed_fields(old, new),
+                )
+            })
+            .collect();
+        assert_eq!(
+            changed,
+            [
+                (Path::new("remapped"), vec!["levelHash".to_string()]),
+                (Path::new("same hash"), vec!["levelId".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_size_and_problem_changes() {
+        let older = song("song", "1111", DEFAULT_ID);
+        let mut newer = older.clone();
+        newer.folder_size = 1024;
+        newer
+            .problems
+            .push(InvalidReason::MissingCover("cover.jpg".to_string()));
+        let mut fields = SnapshotDiff::changed_fields(&older, &newer);
+        fields.sort();
+        assert_eq!(fields, ["folderSize", "problems"]);
+        assert!(SnapshotDiff::changed_fields(&older, &older).is_empty());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut song = song("song", "1111", DEFAULT_ID);
+        song.duration = Some(Duration::from_millis(1500));
+        song.date_added = Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        let folder = TempFolder::new("snapshot");
+        let path = folder.join("snapshot.json");
+        let snapshot = Snapshot::new("songs", &[song, self::song("other", "2222", DEFAULT_ID)]);
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap();
+        assert_eq!(loaded.song_folder(), Path::new("songs"));
+        assert_eq!(loaded.created_at, snapshot.created_at);
+        assert_eq!(loaded.songs[0].duration, Some(Duration::from_millis(1500)));
+        assert_eq!(
+            loaded.songs[0].date_added,
+            Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+        );
+        assert_eq!(loaded.songs[1].duration, None);
+        assert_eq!(loaded.songs[1].date_added, None);
+        assert!(snapshot.diff(&loaded).is_empty());
+    }
+
+    #[test]
+    fn negative_duration_is_dropped() {
+        let mut json = serde_json::to_value(song("song", "1111", DEFAULT_ID)).unwrap();
+        json["duration"] = (-1.0).into();
+        let song: Song = serde_json::from_value(json).unwrap();
+        assert_eq!(song.duration, None);
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::Song;
+
+/// An empty folder under the system temp folder, removed again when dropped, also when the test
+/// panics.
+pub(crate) struct TempFolder(PathBuf);
+
+impl TempFolder {
+    pub(crate) fn new(name: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "bs-song-manager-{}-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempFolder(path)
+    }
+}
+
+impl Deref for TempFolder {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFolder {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A song in `path` named "Song" by "Mapper", so "{level_id} (Song - Mapper)" by the default
+/// template.
+pub(crate) fn song(path: impl AsRef<Path>, level_hash: &str, level_id: &str) -> Song {
+    let mut song = Song::placeholder(path.as_ref());
+    song.song_name = "Song".to_string();
+    song.level_author_name = "Mapper".to_string();
+    song.level_hash = level_hash.to_string();
+    song.level_id = level_id.to_string();
+    song
+}
@@ -0,0 +1,255 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs::{read_dir, File},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Song, DATA_DIR_NAME};
+
+/// A song folder moved to the trash.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrashEntry {
+    /// Name of the folder inside the trash, unique among the entries.
+    pub(crate) id: String,
+    pub(crate) song_name: String,
+    pub(crate) original_path: PathBuf,
+    /// Seconds since the unix epoch.
+    pub(crate) deleted_at: u64,
+}
+
+impl TrashEntry {
+    pub(crate) fn deleted_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.deleted_at)
+    }
+}
+
+/// Library-local trash in the data folder of the song folder.
+///
+/// The layout follows the freedesktop trash: song folders are moved into `files/` and a json
+/// file with the same name in `info/` records where they came from.
+pub(crate) struct Trash {
+    files: PathBuf,
+    info: PathBuf,
+}
+
+impl Trash {
+    pub(crate) fn new(song_folder: &Path) -> Self {
+        let root = song_folder.join(DATA_DIR_NAME).join("trash");
+        Trash {
+            files: root.join("files"),
+            info: root.join("info"),
+        }
+    }
+
+    fn info_path(&self, id: &str) -> PathBuf {
+        self.info.join(format!("{}.json", id))
+    }
+
+    /// Where the song folder of `entry` is kept.
+    pub(crate) fn entry_path(&self, entry: &TrashEntry) -> PathBuf {
+        self.files.join(&entry.id)
+    }
+
+    pub(crate) fn get(&self, id: &str) -> Option<TrashEntry> {
+        let file = File::open(self.info_path(id)).ok()?;
+        serde_json::from_reader(file).ok()
+    }
+
+    /// Move the song folder into the trash.
+    pub(crate) fn put(&self, song: &Song) -> Result<TrashEntry, Box<dyn Error>> {
+        self.put_path(&song.song_folder_path, &song.song_name)
+    }
+
+    /// Move any file or folder into the trash, `name` is shown in the trash view.
+    pub(crate) fn put_path(&self, path: &Path, name: &str) -> Result<TrashEntry, Box<dyn Error>> {
+        std::fs::create_dir_all(&self.files)?;
+        std::fs::create_dir_all(&self.info)?;
+        let deleted_at = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let file_name = path
+            .file_name()
+            .ok_or("path has no name")?
+            .to_string_lossy();
+        let mut id = format!("{}_{}", deleted_at.as_millis(), file_name);
+        let mut suffix = 1;
+        while self.files.join(&id).exists() || self.info_path(&id).exists() {
+            id = format!("{}_{}_{}", deleted_at.as_millis(), file_name, suffix);
+            suffix += 1;
+        }
+        let entry = TrashEntry {
+            id,
+            song_name: name.to_string(),
+            original_path: path.to_path_buf(),
+            deleted_at: deleted_at.as_secs(),
+        };
+        serde_json::to_writer(File::create(self.info_path(&entry.id))?, &entry)?;
+        info!("Moving {} to trash", path.display());
+        if let Err(error) = std::fs::rename(path, self.entry_path(&entry)) {
+            let _ = std::fs::remove_file(self.info_path(&entry.id));
+            return Err(error.into());
+        }
+        Ok(entry)
+    }
+
+    /// All entries in the trash, the most recently deleted first.
+    pub(crate) fn entries(&self) -> Vec<TrashEntry> {
+        let info_entries = match read_dir(&self.info) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        let mut entries: Vec<TrashEntry> = info_entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                match File::open(&path)
+                    .map_err(Box::<dyn Error>::from)
+                    .and_then(|file| Ok(serde_json::from_reader(file)?))
+                {
+                    Ok(entry) => Some(entry),
+                    Err(error) => {
+                        warn!("Read trash info {} failed. {}", path.display(), error);
+                        None
+                    }
+                }
+            })
+            .collect();
+        entries.sort_by_key(|entry: &TrashEntry| std::cmp::Reverse(entry.deleted_at));
+        entries
+    }
+
+    /// Move the song folder back to where it was deleted from.
+    pub(crate) fn restore(&self, entry: &TrashEntry) -> Result<(), Box<dyn Error>> {
+        if entry.original_path.exists() {
+            return Err(format!("{} already exists", entry.original_path.display()).into());
+        }
+        info!("Restoring {}", entry.original_path.display());
+        std::fs::rename(self.entry_path(entry), &entry.original_path)?;
+        std::fs::remove_file(self.info_path(&entry.id))?;
+        Ok(())
+    }
+
+    /// Delete the song folder permanently.
+    pub(crate) fn remove(&self, entry: &TrashEntry) -> Result<(), Box<dyn Error>> {
+        info!("Deleting {} from trash", entry.original_path.display());
+        let files_path = self.entry_path(entry);
+        if files_path.is_dir() {
+            std::fs::remove_dir_all(files_path)?;
+        } else if files_path.exists() {
+            std::fs::remove_file(files_path)?;
+        }
+        std::fs::remove_file(self.info_path(&entry.id))?;
+        Ok(())
+    }
+
+    pub(crate) fn empty(&self) {
+        for entry in self.entries() {
+            if let Err(error) = self.remove(&entry) {
+                warn!("Empty trash failed. {}", error);
+            }
+        }
+    }
+
+    /// Permanently delete the entries that have been in the trash longer than `retention`, except
+    /// those in `referenced` that a commit can still be undone with.
+    pub(crate) fn purge_expired(&self, retention: Duration, referenced: &HashSet<String>) {
+        let now = SystemTime::now();
+        for entry in self.entries() {
+            if referenced.contains(&entry.id) {
+                continue;
+            }
+            let expired = match now.duration_since(entry.deleted_time()) {
+                Ok(age) => age > retention,
+                Err(_) => false,
+            };
+            if expired {
+                if let Err(error) = self.remove(&entry) {
+                    warn!("Purge trash failed. {}", error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFolder;
+
+    /// A song folder holding one folder `song`.
+    fn folder_with_song(name: &str) -> TempFolder {
+        let song_folder = TempFolder::new(name);
+        std::fs::create_dir(song_folder.join("song")).unwrap();
+        std::fs::write(song_folder.join("song").join("Info.dat"), "{}").unwrap();
+        song_folder
+    }
+
+    #[test]
+    fn put_and_restore() {
+        let song_folder = folder_with_song("restore");
+        let trash = Trash::new(&song_folder);
+        let entry = trash.put_path(&song_folder.join("song"), "Song").unwrap();
+        assert!(!song_folder.join("song").exists());
+        assert!(trash.entry_path(&entry).join("Info.dat").is_file());
+        assert_eq!(trash.entries().len(), 1);
+        assert_eq!(trash.get(&entry.id).unwrap().song_name, "Song");
+        trash.restore(&entry).unwrap();
+        assert!(song_folder.join("song").join("Info.dat").is_file());
+        assert!(trash.entries().is_empty());
+    }
+
+    #[test]
+    fn restore_keeps_entry_when_folder_exists_again() {
+        let song_folder = folder_with_song("taken");
+        let trash = Trash::new(&song_folder);
+        let entry = trash.put_path(&song_folder.join("song"), "Song").unwrap();
+        std::fs::create_dir(song_folder.join("song")).unwrap();
+        assert!(trash.restore(&entry).is_err());
+        assert!(trash.entry_path(&entry).is_dir());
+        assert_eq!(trash.entries().len(), 1);
+    }
+
+    #[test]
+    fn same_name_gets_unique_ids() {
+        let song_folder = folder_with_song("unique");
+        let trash = Trash::new(&song_folder);
+        let first = trash.put_path(&song_folder.join("song"), "Song").unwrap();
+        std::fs::create_dir(song_folder.join("song")).unwrap();
+        let second = trash.put_path(&song_folder.join("song"), "Song").unwrap();
+        assert_ne!(first.id, second.id);
+        assert_eq!(trash.entries().len(), 2);
+        trash.empty();
+        assert!(trash.entries().is_empty());
+        assert!(!trash.entry_path(&first).exists());
+    }
+
+    #[test]
+    fn purges_only_expired_entries() {
+        let song_folder = folder_with_song("purge");
+        std::fs::create_dir(song_folder.join("new")).unwrap();
+        let trash = Trash::new(&song_folder);
+        let mut old = trash.put_path(&song_folder.join("song"), "Old").unwrap();
+        old.deleted_at -= 3 * 24 * 60 * 60;
+        serde_json::to_writer(File::create(trash.info_path(&old.id)).unwrap(), &old).unwrap();
+        let new = trash.put_path(&song_folder.join("new"), "New").unwrap();
+        trash.purge_expired(Duration::from_secs(24 * 60 * 60), &HashSet::new());
+        let ids: Vec<String> = trash.entries().into_iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [new.id]);
+        assert!(!trash.entry_path(&old).exists());
+    }
+
+    #[test]
+    fn purge_keeps_referenced_entries() {
+        let song_folder = folder_with_song("referenced");
+        let trash = Trash::new(&song_folder);
+        let mut old = trash.put_path(&song_folder.join("song"), "Old").unwrap();
+        old.deleted_at -= 3 * 24 * 60 * 60;
+        serde_json::to_writer(File::create(trash.info_path(&old.id)).unwrap(), &old).unwrap();
+        let referenced = HashSet::from([old.id.clone()]);
+        trash.purge_expired(Duration::from_secs(24 * 60 * 60), &referenced);
+        assert!(trash.entry_path(&old).is_dir());
+        assert_eq!(trash.entries().len(), 1);
+    }
+}
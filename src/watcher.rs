@@ -0,0 +1,179 @@
+use log::{debug, info, warn};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, SendError, Sender},
+        Arc, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    index::{file_stamps, LibraryIndex},
+    load_id_cache, save_id_cache, update_id_cache, InvalidReason, ScanProgress, Song,
+    DATA_DIR_NAME,
+};
+
+/// How long a song folder has to stay untouched before it is loaded again, downloaders write
+/// the files of a map one after another.
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// A song folder that was added, modified or removed after the scan.
+pub(crate) enum FolderChange {
+    Song(Box<Song>),
+    Invalid(PathBuf, InvalidReason),
+    Removed(PathBuf),
+}
+
+/// Watches a song folder for changes and repaints `ctx` when there are some, dropping it stops
+/// watching.
+pub(crate) struct SongFolderWatcher {
+    song_path: PathBuf,
+    _watcher: RecommendedWatcher,
+    pub(crate) receiver: Receiver<FolderChange>,
+}
+
+impl SongFolderWatcher {
+    pub(crate) fn start(song_path: PathBuf, ctx: egui::Context) -> notify::Result<Self> {
+        let (event_sender, event_receiver) = channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+                Ok(event) => {
+                    // The worker is gone once the watcher is being dropped.
+                    let _ = event_sender.send(event.paths);
+                }
+                Err(error) => warn!("Watch song folder failed. {}", error),
+            })?;
+        watcher.watch(&song_path, RecursiveMode::Recursive)?;
+        info!("Watching {} for changes.", song_path.display());
+        let (sender, receiver) = channel();
+        let song_path_cloned = song_path.clone();
+        thread::spawn(move || watch_song_folder(&song_path_cloned, &event_receiver, &sender, &ctx));
+        Ok(SongFolderWatcher {
+            song_path,
+            _watcher: watcher,
+            receiver,
+        })
+    }
+
+    pub(crate) fn song_path(&self) -> &Path {
+        &self.song_path
+    }
+}
+
+/// The entry directly inside `song_path` that `path` belongs to, `None` for the files the app
+/// keeps in the song folder itself.
+fn song_folder_of(song_path: &Path, canonical_path: &Path, path: &Path) -> Option<PathBuf> {
+    let relative = path
+        .strip_prefix(song_path)
+        .or_else(|_| path.strip_prefix(canonical_path))
+        .ok()?;
+    let folder = song_path.join(relative.components().next()?);
+    if folder.ends_with(DATA_DIR_NAME) || folder.ends_with("id.cache") {
+        return None;
+    }
+    Some(folder)
+}
+
+/// Collect the song folders touched by `events` and reload each once it has settled, until
+/// the watcher is dropped.
+fn watch_song_folder(
+    song_path: &Path,
+    events: &Receiver<Vec<PathBuf>>,
+    sender: &Sender<FolderChange>,
+    ctx: &egui::Context,
+) {
+    // Some platforms report events under the resolved path of the watched folder.
+    let canonical_path = song_path
+        .canonicalize()
+        .unwrap_or_else(|_| song_path.to_path_buf());
+    let mut changed: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        match events.recv_timeout(SETTLE_TIME / 2) {
+            Ok(paths) => {
+                for path in paths {
+                    if let Some(folder) = song_folder_of(song_path, &canonical_path, &path) {
+                        changed.insert(folder, Instant::now());
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        let settled: Vec<PathBuf> = changed
+            .iter()
+            .filter(|(_, time)| time.elapsed() >= SETTLE_TIME)
+            .map(|(folder, _)| folder.clone())
+            .collect();
+        if settled.is_empty() {
+            continue;
+        }
+        for folder in &settled {
+            changed.remove(folder);
+        }
+        if reload_folders(song_path, settled, sender).is_err() {
+            break;
+        }
+        ctx.request_repaint();
+    }
+    debug!("Stopped watching {}.", song_path.display());
+}
+
+/// Load `folders` again, send what became of each of them and update the library index.
+///
+/// A scan running at the same time saves its own index afterwards, losing that race only
+/// means the folders are parsed again by the next scan.
+fn reload_folders(
+    song_path: &Path,
+    folders: Vec<PathBuf>,
+    sender: &Sender<FolderChange>,
+) -> Result<(), SendError<FolderChange>> {
+    let pending_hash_list = Arc::new(RwLock::new(VecDeque::new()));
+    let cached_id = Arc::new(RwLock::new(load_id_cache(song_path)));
+    let mut index = LibraryIndex::load(song_path);
+    let mut songs = Vec::new();
+    for folder in folders {
+        if !folder.exists() {
+            info!("Song folder {} was removed.", folder.display());
+            index.remove(&folder);
+            sender.send(FolderChange::Removed(folder))?;
+        } else if !folder.is_dir() {
+            index.remove(&folder);
+            sender.send(FolderChange::Invalid(folder, InvalidReason::NotADirectory))?;
+        } else {
+            info!("Reloading song from {}.", folder.display());
+            let files = file_stamps(&folder);
+            let song = Song::from_path(&folder, &pending_hash_list, &cached_id);
+            index.insert(&folder, files, song.as_ref());
+            match song {
+                Ok(song) => songs.push(song),
+                Err(reason) => sender.send(FolderChange::Invalid(folder, reason))?,
+            }
+        }
+    }
+    if let Err(error) = index.save(song_path) {
+        warn!("Save library index failed. {}", error);
+    }
+    let pending_hash_list = pending_hash_list.read().unwrap().clone();
+    if !pending_hash_list.is_empty() {
+        if let Err(error) = update_id_cache(pending_hash_list, &cached_id, &ScanProgress::default())
+        {
+            warn!("Failed to connect api server.{}", error);
+        }
+        // Ids resolved before a failed request are kept too.
+        save_id_cache(song_path, &cached_id.read().unwrap());
+        let id_cache = cached_id.read().unwrap();
+        for song in songs.iter_mut() {
+            if let Some(id) = id_cache.get(&song.level_hash) {
+                song.level_id = id.clone();
+            }
+        }
+    }
+    for song in songs {
+        sender.send(FolderChange::Song(Box::new(song)))?;
+    }
+    Ok(())
+}